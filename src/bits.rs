@@ -61,11 +61,13 @@ int_bitset!(u8);
 int_bitset!(u16);
 int_bitset!(u32);
 int_bitset!(u64);
+int_bitset!(u128);
 int_bitset!(usize);
 int_bitset!(i8);
 int_bitset!(i16);
 int_bitset!(i32);
 int_bitset!(i64);
+int_bitset!(i128);
 int_bitset!(isize);
 
 impl BitSetLike for BitSet {
@@ -215,10 +217,12 @@ int_api!(u8, 8);
 int_api!(u16, 16);
 int_api!(u32, 32);
 int_api!(u64, 64);
+int_api!(u128, 128);
 int_api!(i8, 8);
 int_api!(i16, 16);
 int_api!(i32, 32);
 int_api!(i64, 64);
+int_api!(i128, 128);
 
 macro_rules! minimal_api {
     ($md:ident, $typ:ty) => {
@@ -327,4 +331,19 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn u128_bits_above_64_are_generated() {
+        let mut accum = 0u128;
+
+        let mut runner = TestRunner::new(Config::default());
+        let input = u128::between(60, 128);
+        for _ in 0..256 {
+            let value = input.new_value(&mut runner).unwrap().current();
+            assert_eq!(0, value & ((1 << 60) - 1));
+            accum |= value;
+        }
+
+        assert_eq!(!0u128 << 60, accum);
+    }
 }
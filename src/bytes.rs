@@ -0,0 +1,239 @@
+//-
+// Copyright 2017 Jason Lingle
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Strategies for generating byte strings which are almost, but not quite,
+//! valid UTF-8, for testing decoders.
+//!
+//! For byte strings matching a pattern, see `string::bytes_regex()` instead.
+
+use std::str;
+
+use char;
+use strategy::*;
+use string;
+use test_runner::*;
+
+opaque_strategy_wrapper! {
+    /// Strategy which generates byte strings for testing decoders.
+    ///
+    /// Created by the `malformed_utf8()` and `wtf8()` functions in the same
+    /// module.
+    #[derive(Debug)]
+    pub struct DecoderTestStrategy[][](BoxedStrategy<Vec<u8>>)
+        -> DecoderTestValueTree;
+    /// `ValueTree` corresponding to `DecoderTestStrategy`.
+    pub struct DecoderTestValueTree[][](Box<ValueTree<Value = Vec<u8>>>)
+        -> Vec<u8>;
+}
+
+/// Creates a strategy which generates byte strings which are not valid
+/// UTF-8, consisting of valid text interspersed with at least one of the
+/// following defects:
+///
+/// - A continuation byte (`0x80` to `0xBF`) with no preceding lead byte.
+///
+/// - A multi-byte sequence which is cut short.
+///
+/// - A multi-byte sequence with a byte which is not a continuation byte in
+///   place of one of its continuation bytes.
+///
+/// - An overlong encoding, i.e., one using more bytes than necessary, such as
+///   `C0 AF` for `/`.
+///
+/// - An encoded surrogate code point (`U+D800` to `U+DFFF`).
+///
+/// - An encoded code point beyond `U+10FFFF`.
+///
+/// Values shrink toward fewer defects, and toward defects earlier in the
+/// above list.
+pub fn malformed_utf8() -> DecoderTestStrategy {
+    let segment = Union::new_weighted(vec![
+        (4, text()),
+        (1, (0x80u8..0xC0).prop_map(|b| vec![b]).boxed()),
+        (1, (multi_byte_char(), 1..4usize).prop_map(|(c, len)| {
+            let bytes = encode_char(c);
+            bytes[..len % (bytes.len() - 1) + 1].to_vec()
+        }).boxed()),
+        (1, (multi_byte_char(), 1..4usize, non_continuation())
+         .prop_map(|(c, pos, b)| {
+             let mut bytes = encode_char(c);
+             let pos = pos % (bytes.len() - 1) + 1;
+             bytes[pos] = b;
+             bytes
+         }).boxed()),
+        (1, (0..0x10000u32, 0..3usize).prop_map(|(cp, extra)| {
+            let len = encoded_len(cp);
+            encode(cp, len + 1 + extra % (4 - len))
+        }).boxed()),
+        (1, (0xD800..0xE000u32).prop_map(|cp| encode(cp, 3)).boxed()),
+        (1, (0x110000..0x200000u32).prop_map(|cp| encode(cp, 4)).boxed()),
+    ]);
+
+    DecoderTestStrategy(
+        ::collection::vec(segment, 1..8)
+            .prop_map(|segments| segments.concat())
+            .prop_filter("no defects".to_owned(),
+                         |bytes| str::from_utf8(bytes).is_err())
+            .boxed())
+}
+
+/// Creates a strategy which generates WTF-8, i.e., UTF-8 which may
+/// additionally contain encoded surrogate code points (`U+D800` to `U+DFFF`)
+/// as long as they do not form a pair. This is the encoding used for
+/// `OsStr` on Windows, where file names may contain unpaired surrogates.
+///
+/// Values shrink toward fewer surrogates and shorter text.
+pub fn wtf8() -> DecoderTestStrategy {
+    let segment = Union::new_weighted(vec![
+        (4, text()),
+        (1, (0xD800..0xE000u32).prop_map(|cp| encode(cp, 3)).boxed()),
+    ]);
+
+    DecoderTestStrategy(
+        ::collection::vec(segment, 0..8)
+            .prop_map(|segments| segments.concat())
+            .prop_filter("contains surrogate pair".to_owned(),
+                         |bytes| !has_surrogate_pair(bytes))
+            .boxed())
+}
+
+fn text() -> BoxedStrategy<Vec<u8>> {
+    string::string_of(char::ANY, 1..8).prop_map(String::into_bytes).boxed()
+}
+
+fn multi_byte_char() -> char::CharStrategy<'static> {
+    char::range('\u{80}', ::std::char::MAX)
+}
+
+fn non_continuation() -> BoxedStrategy<u8> {
+    Union::new(vec![(0u8..0x80).boxed(), (0xC0u8..=0xFF).boxed()]).boxed()
+}
+
+/// Returns the number of bytes in the shortest encoding of `cp`.
+fn encoded_len(cp: u32) -> usize {
+    match cp {
+        0..=0x7F => 1,
+        0x80..=0x7FF => 2,
+        0x800..=0xFFFF => 3,
+        _ => 4,
+    }
+}
+
+fn encode_char(c: char) -> Vec<u8> {
+    encode(c as u32, encoded_len(c as u32))
+}
+
+/// Encodes `cp` in `len` bytes using the UTF-8 bit layout, without checking
+/// whether it is a valid or the shortest encoding.
+fn encode(cp: u32, len: usize) -> Vec<u8> {
+    if 1 == len {
+        return vec![cp as u8];
+    }
+
+    let lead_marker = [0, 0, 0xC0, 0xE0, 0xF0][len];
+    let mut bytes = vec![0u8; len];
+    for (ix, byte) in bytes.iter_mut().enumerate().skip(1) {
+        *byte = 0x80 | (cp >> (6 * (len - 1 - ix)) & 0x3F) as u8;
+    }
+    bytes[0] = lead_marker | (cp >> (6 * (len - 1))) as u8;
+    bytes
+}
+
+fn has_surrogate_pair(bytes: &[u8]) -> bool {
+    // High surrogates are encoded as `ED A0..AF xx` and low surrogates as
+    // `ED B0..BF xx`.
+    bytes.windows(6).any(|w| 0xED == w[0] && (0xA0..0xB0).contains(&w[1]) &&
+                         0xED == w[3] && (0xB0..0xC0).contains(&w[4]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_matches_std() {
+        for &c in &['\0', 'a', '\u{7FF}', '\u{800}', '\u{FFFF}', '\u{10000}',
+                    '\u{10FFFF}'] {
+            assert_eq!(c.to_string().into_bytes(), encode_char(c));
+        }
+        assert_eq!(vec![0xC0, 0xAF], encode('/' as u32, 2));
+        assert_eq!(vec![0xED, 0xA0, 0x80], encode(0xD800, 3));
+    }
+
+    #[test]
+    fn malformed_utf8_is_never_valid() {
+        let strategy = malformed_utf8();
+        let mut runner = TestRunner::new(Config::default());
+        let mut with_text = 0;
+        for _ in 0..256 {
+            let mut value = strategy.new_value(&mut runner).unwrap();
+            if String::from_utf8_lossy(&value.current()).chars()
+                .any(|c| '\u{FFFD}' != c)
+            {
+                with_text += 1;
+            }
+
+            loop {
+                let bytes = value.current();
+                assert!(str::from_utf8(&bytes).is_err(),
+                        "Valid UTF-8: {:?}", bytes);
+                if !value.simplify() { break; }
+            }
+        }
+        assert!(with_text > 128, "Only {} with valid text", with_text);
+    }
+
+    #[test]
+    fn malformed_utf8_shrinks_to_single_defect() {
+        let strategy = malformed_utf8();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let case = strategy.new_value(&mut runner).unwrap();
+            let result = runner.run_one(
+                case, |_| Err(TestCaseError::Fail("fail".to_owned())));
+            match result {
+                Err(TestError::Fail(_, bytes)) => {
+                    assert_eq!(1, bytes.len(), "Not minimal: {:?}", bytes);
+                    assert!((0x80..0xC0).contains(&bytes[0]));
+                },
+                e => panic!("Unexpected result: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn wtf8_contains_unpaired_surrogates() {
+        let strategy = wtf8();
+        let mut runner = TestRunner::new(Config::default());
+        let mut surrogates = 0;
+        for _ in 0..256 {
+            let mut value = strategy.new_value(&mut runner).unwrap();
+            if str::from_utf8(&value.current()).is_err() {
+                surrogates += 1;
+            }
+
+            loop {
+                let bytes = value.current();
+                assert!(!has_surrogate_pair(&bytes), "Pair in {:?}", bytes);
+                // Replacing each surrogate with a 3-byte character makes it
+                // valid UTF-8.
+                let mut fixed = bytes.clone();
+                for ix in 0..fixed.len() {
+                    if 0xED == fixed[ix] && fixed[ix + 1] >= 0xA0 {
+                        fixed[ix + 1] = 0x80;
+                    }
+                }
+                assert!(str::from_utf8(&fixed).is_ok(),
+                        "Not WTF-8: {:?}", bytes);
+                if !value.simplify() { break; }
+            }
+        }
+        assert!(surrogates > 32, "Only {} with surrogates", surrogates);
+    }
+}
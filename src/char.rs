@@ -14,17 +14,32 @@
 //! circumstances.
 //!
 //! The main things of interest are `ANY` to generate truly arbitrary
-//! characters, and `range()` and `ranges()` to select characters from
-//! inclusive ranges.
+//! characters, `range()` and `ranges()` to select characters from
+//! inclusive ranges, and `category()` and `script()` to select characters
+//! from Unicode character classes.
 
 use std::borrow::Cow;
 
 use rand::Rng;
+use regex_syntax as rs;
+use regex_syntax::hir::{Class, HirKind};
 
 use num;
 use strategy::*;
 use test_runner::*;
 
+quick_error! {
+    /// Errors which may occur when looking up a Unicode character class.
+    #[derive(Debug)]
+    pub enum Error {
+        /// The name is not that of a known class of the requested kind.
+        UnknownClass(name: String) {
+            description("unknown Unicode character class")
+            display("unknown Unicode character class `{}`", name)
+        }
+    }
+}
+
 /// An inclusive char range from fst to snd.
 /// TODO: replace with `std::ops::RangeInclusive<char>` once stabilized.
 type CharRange = (char, char);
@@ -147,7 +162,9 @@ fn select_range_index<R : Rng>(rnd: &mut R,
 ///
 /// Shrinking never crosses ranges. If you have a complex range like `[A-Za-z]`
 /// and the starting point `x` is chosen, it will not shrink to the first `A-Z`
-/// group, but rather simply to `a`.
+/// group, but rather simply to `a`. The exception is strategies for Unicode
+/// character classes, such as from `category()`, which finally try the
+/// simplest member of the whole class once shrinking within a range is done.
 ///
 /// The usual way to get instances of this class is with the module-level `ANY`
 /// constant or `range` function. Directly constructing a `CharStrategy` is
@@ -157,6 +174,7 @@ pub struct CharStrategy<'a> {
     special: Cow<'a, [char]>,
     preferred: Cow<'a, [CharRange]>,
     ranges: Cow<'a, [CharRange]>,
+    simplest: Option<char>,
 }
 
 impl<'a> CharStrategy<'a> {
@@ -171,6 +189,7 @@ impl<'a> CharStrategy<'a> {
             special: special,
             preferred: preferred,
             ranges: ranges,
+            simplest: None,
         }
     }
 
@@ -196,6 +215,7 @@ pub const ANY: CharStrategy<'static> = CharStrategy {
     special: Cow::Borrowed(DEFAULT_SPECIAL_CHARS),
     preferred: Cow::Borrowed(DEFAULT_PREFERRED_RANGES),
     ranges: Cow::Borrowed(WHOLE_RANGE),
+    simplest: None,
 };
 
 /// Creates a `CharStrategy` which selects characters within the given
@@ -205,6 +225,7 @@ pub fn range(start: char, end: char) -> CharStrategy<'static> {
         special: Cow::Borrowed(DEFAULT_SPECIAL_CHARS),
         preferred: Cow::Borrowed(DEFAULT_PREFERRED_RANGES),
         ranges: Cow::Owned(vec![(start, end)]),
+        simplest: None,
     }
 }
 
@@ -215,13 +236,109 @@ pub fn ranges(ranges: Cow<[CharRange]>) -> CharStrategy {
         special: Cow::Borrowed(DEFAULT_SPECIAL_CHARS),
         preferred: Cow::Borrowed(DEFAULT_PREFERRED_RANGES),
         ranges: ranges,
+        simplest: None,
+    }
+}
+
+/// Creates a `CharStrategy` which selects characters in the given Unicode
+/// general category, using the default biases.
+///
+/// `name` may be a one-letter major category such as `L` (letters) or `P`
+/// (punctuation), a two-letter category such as `Lu` (uppercase letters) or
+/// `Nd` (decimal digits), or the long form of either, such as `Letter` or
+/// `Decimal_Number`. Names are matched ignoring case, spaces, `-` and `_`.
+///
+/// Values shrink within their range as for other `CharStrategy`s, and then
+/// to the simplest member of the category: `a`, `A`, `0` or space if it
+/// contains one of those, otherwise the first character of the category.
+///
+/// The Unicode tables are those of the `regex-syntax` crate.
+pub fn category(name: &str) -> Result<CharStrategy<'static>, Error> {
+    unicode_class("gc", name)
+}
+
+/// Creates a `CharStrategy` which selects characters in the given Unicode
+/// script, such as `Greek` or `Han`, using the default biases.
+///
+/// Script names are matched as for `category()`, and values shrink in the
+/// same way.
+pub fn script(name: &str) -> Result<CharStrategy<'static>, Error> {
+    unicode_class("sc", name)
+}
+
+/// Creates a `CharStrategy` which selects letters, i.e., characters in the
+/// Unicode general category `L`.
+pub fn letter() -> CharStrategy<'static> {
+    category("L").expect("letter category missing")
+}
+
+/// Creates a `CharStrategy` which selects decimal digits, i.e., characters in
+/// the Unicode general category `Nd`, which includes digits of many scripts
+/// besides `0` to `9`.
+pub fn digit() -> CharStrategy<'static> {
+    category("Nd").expect("digit category missing")
+}
+
+/// Creates a `CharStrategy` which selects punctuation, i.e., characters in
+/// the Unicode general category `P`.
+pub fn punctuation() -> CharStrategy<'static> {
+    category("P").expect("punctuation category missing")
+}
+
+/// Creates a `CharStrategy` which selects characters with the Unicode
+/// `White_Space` property, which includes line breaks such as `\n` and
+/// U+2028 LINE SEPARATOR as well as spaces.
+pub fn whitespace() -> CharStrategy<'static> {
+    unicode_class("", "White_Space").expect("whitespace property missing")
+}
+
+/// Looks up the given property, like `\p{property=name}` in a regex, or
+/// `\p{name}` if `property` is empty.
+fn unicode_class(property: &str, name: &str)
+                 -> Result<CharStrategy<'static>, Error> {
+    let unknown = || Error::UnknownClass(name.to_owned());
+
+    // Anything else would be interpreted by the regex parser.
+    if name.is_empty() || !name.chars().all(
+        |c| c.is_ascii_alphanumeric() || " _-".contains(c))
+    {
+        return Err(unknown());
     }
+
+    let pattern = if property.is_empty() {
+        format!("\\p{{{}}}", name)
+    } else {
+        format!("\\p{{{}={}}}", property, name)
+    };
+    let hir = rs::parse(&pattern).map_err(|_| unknown())?;
+    let ranges = match *hir.kind() {
+        HirKind::Class(Class::Unicode(ref class)) => class.ranges().iter()
+            .map(|r| (r.start(), r.end())).collect::<Vec<_>>(),
+        _ => return Err(unknown()),
+    };
+
+    let contains = |c: char| ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+    let simplest = ['a', 'A', '0', ' '].iter().cloned().find(|&c| contains(c))
+        .unwrap_or(ranges[0].0);
+
+    Ok(CharStrategy {
+        special: Cow::Borrowed(DEFAULT_SPECIAL_CHARS),
+        preferred: Cow::Borrowed(DEFAULT_PREFERRED_RANGES),
+        ranges: Cow::Owned(ranges),
+        simplest: Some(simplest),
+    })
 }
 
 /// The `ValueTree` corresponding to `CharStrategy`.
 #[derive(Debug, Clone, Copy)]
 pub struct CharValueTree {
     value: num::u32::BinarySearch,
+    /// The simplest member of the whole class, if the strategy was for a
+    /// Unicode class, which is tried once `value` cannot be simplified.
+    simplest: Option<char>,
+    /// The value from before trying `simplest`, if that was the last
+    /// simplification.
+    before_simplest: Option<num::u32::BinarySearch>,
 }
 
 impl<'a> Strategy for CharStrategy<'a> {
@@ -249,7 +366,9 @@ impl<'a> Strategy for CharStrategy<'a> {
         };
 
         Ok(CharValueTree {
-            value: num::u32::BinarySearch::new_above(bottom, start)
+            value: num::u32::BinarySearch::new_above(bottom, start),
+            simplest: self.simplest,
+            before_simplest: None,
         })
     }
 }
@@ -273,16 +392,28 @@ impl ValueTree for CharValueTree {
     }
 
     fn simplify(&mut self) -> bool {
+        self.before_simplest = None;
         if self.value.simplify() {
             self.reposition();
             true
+        } else if let Some(simplest) = self.simplest.take() {
+            if simplest == self.current() {
+                return false;
+            }
+            self.before_simplest = Some(self.value);
+            self.value = num::u32::BinarySearch::new_above(
+                simplest as u32, simplest as u32);
+            true
         } else {
             false
         }
     }
 
     fn complicate(&mut self) -> bool {
-        if self.value.complicate() {
+        if let Some(value) = self.before_simplest.take() {
+            self.value = value;
+            true
+        } else if self.value.complicate() {
             self.reposition();
             true
         } else {
@@ -368,4 +499,84 @@ mod test {
 
         assert!(accepted >= 200);
     }
+
+    #[test]
+    fn unicode_classes_stay_in_class() {
+        let upper = category("Lu").unwrap();
+        let greek = script("Greek").unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let mut value = upper.new_value(&mut runner).unwrap();
+            loop {
+                assert!(value.current().is_uppercase(),
+                        "Not uppercase: {:?}", value.current());
+                if !value.simplify() { break; }
+            }
+            assert_eq!('A', value.current());
+
+            let mut value = greek.new_value(&mut runner).unwrap();
+            loop {
+                let ch = value.current();
+                assert!(('\u{370}'..='\u{3FF}').contains(&ch) ||
+                        ('\u{1D00}'..='\u{1FFF}').contains(&ch) ||
+                        ('\u{10140}'..='\u{101A0}').contains(&ch) ||
+                        ('\u{1D200}'..='\u{1D24F}').contains(&ch) ||
+                        '\u{2126}' == ch || '\u{AB65}' == ch,
+                        "Not Greek: {:?}", ch);
+                if !value.simplify() { break; }
+            }
+        }
+    }
+
+    #[test]
+    fn convenience_classes() {
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            assert!(letter().new_value(&mut runner).unwrap().current()
+                    .is_alphabetic());
+            assert!(whitespace().new_value(&mut runner).unwrap().current()
+                    .is_whitespace());
+            let mut digit = digit().new_value(&mut runner).unwrap();
+            assert!(digit.current().is_numeric());
+            while digit.simplify() { }
+            assert_eq!('0', digit.current());
+            let p = punctuation().new_value(&mut runner).unwrap().current();
+            assert!(!p.is_alphanumeric() && !p.is_whitespace(),
+                    "Not punctuation: {:?}", p);
+        }
+    }
+
+    #[test]
+    fn shrinks_toward_simplest_member() {
+        let strategy = category("Ll").unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let case = strategy.new_value(&mut runner).unwrap();
+            // Pretend that every letter but `x` fails.
+            let result = runner.run_one(case, |&c| if 'x' == c {
+                Ok(())
+            } else {
+                Err(TestCaseError::Fail("not x".to_owned()))
+            });
+
+            match result {
+                Ok(_) => (),
+                Err(TestError::Fail(_, c)) => assert_eq!('a', c),
+                e => panic!("Unexpected result: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_classes() {
+        for name in &["Greek", "Nope", "", "L}|.", "L=Lu"] {
+            match category(name) {
+                Err(Error::UnknownClass(ref n)) if n == name => (),
+                r => panic!("Unexpected result for {:?}: {:?}", name, r),
+            }
+        }
+        assert!(script("Lu").is_err());
+        assert!(script("latin").is_ok());
+        assert!(category("uppercase letter").is_ok());
+    }
 }
@@ -0,0 +1,1065 @@
+//-
+// Copyright 2017 Jason Lingle
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Strategies for generating strings from context-free grammars.
+//!
+//! Regular expressions cannot describe nested formats such as JSON or most
+//! programming languages. A `Grammar` can; it is either parsed from an
+//! EBNF-like notation with `Grammar::parse()` or built from `Production`s with
+//! `Grammar::new()`, and is itself a `Strategy` generating `String`s.
+//!
+//! ## Notation
+//!
+//! A grammar consists of rules of the form `name = expansion ;`. The first
+//! rule is the one generated. `::=` or `:` may be used instead of `=`, names
+//! may be written in angle brackets as in `<digit>`, and the `;` is optional.
+//! Within an expansion:
+//!
+//! - `"text"` or `'text'` is literal text. The escapes `\\`, `\"`, `\'`,
+//!   `\n`, `\r` and `\t` are understood.
+//!
+//! - `/regex/` is any text matching the regex, as with `string_regex()`. A
+//!   `/` within the regex must be written `\/`.
+//!
+//! - `name` expands the named rule.
+//!
+//! - `a b` (or `a, b`) is `a` followed by `b`, and `a | b` is either.
+//!
+//! - `( ... )` groups, `[ ... ]` is optional and `{ ... }` is repeated zero or
+//!   more times. The postfix operators `?`, `*` and `+` are also supported.
+//!
+//! Comments start with `#` and run to the end of the line, or are enclosed in
+//! `(*` and `*)`.
+//!
+//! ## Example
+//!
+//! ```
+//! #[macro_use] extern crate proptest;
+//! use proptest::grammar::Grammar;
+//!
+//! fn arithmetic() -> Grammar {
+//!   Grammar::parse(r#"
+//!     expr   = term { ("+" | "-") term } ;
+//!     term   = factor { ("*" | "/") factor } ;
+//!     factor = number | "(" expr ")" ;
+//!     number = /[1-9][0-9]{0,3}/ ;
+//!   "#).unwrap()
+//! }
+//!
+//! proptest! {
+//!   # /*
+//!   #[test]
+//!   # */
+//!   fn parentheses_balance(ref s in arithmetic()) {
+//!     prop_assert_eq!(s.matches('(').count(), s.matches(')').count());
+//!   }
+//! }
+//! #
+//! # fn main() { parentheses_balance(); }
+//! ```
+//!
+//! ## Generation and shrinking
+//!
+//! Like `prop_recursive()`, generation is limited both in depth, i.e., how
+//! many rules may be nested within each other, and in size, i.e., the total
+//! number of rules expanded. Once either limit is reached, every remaining
+//! rule is given its shortest expansion. Use `Grammar::with_limits()` to
+//! change the limits.
+//!
+//! Values shrink by replacing the expansions of rules with their shortest
+//! ones, trying the outermost rules first, and then by shrinking the text
+//! generated for regexes.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::u32;
+use std::u64;
+
+use rand::Rng;
+
+use string::{self, RegexGeneratorStrategy, RegexGeneratorValueTree};
+use strategy::*;
+use test_runner::*;
+
+quick_error! {
+    /// Errors which may occur when building a `Grammar`.
+    #[derive(Debug)]
+    pub enum Error {
+        /// The grammar text is not syntactically valid.
+        Syntax(offset: usize, message: &'static str) {
+            description(message)
+            display("{} at offset {}", message, offset)
+        }
+        /// The grammar has no rules.
+        NoRules {
+            description("grammar has no rules")
+        }
+        /// A rule is defined more than once.
+        DuplicateRule(name: String) {
+            description("rule defined more than once")
+            display("rule `{}` is defined more than once", name)
+        }
+        /// A rule is used but never defined.
+        UndefinedRule(name: String) {
+            description("rule used but not defined")
+            display("rule `{}` is used but not defined", name)
+        }
+        /// A rule can never finish expanding, as in `a = "x" a ;`.
+        InfiniteRule(name: String) {
+            description("rule can never finish expanding")
+            display("rule `{}` can never finish expanding", name)
+        }
+        /// A regex used in the grammar cannot be used for generation.
+        Regex(regex: String, err: string::Error) {
+            cause(err)
+            description("invalid regex in grammar")
+            display("invalid regex /{}/ in grammar: {}", regex, err)
+        }
+    }
+}
+
+/// The expansion of a rule in a grammar built with `Grammar::new()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Production {
+    /// Literal text.
+    Literal(String),
+    /// Any text matching the given regex.
+    Regex(String),
+    /// The expansion of the named rule.
+    Rule(String),
+    /// Each of the productions, one after the other.
+    Sequence(Vec<Production>),
+    /// Any one of the productions.
+    Choice(Vec<Production>),
+    /// The production or nothing.
+    Optional(Box<Production>),
+    /// The production repeated at least `min` and at most `max` times. If
+    /// `max` is `None`, there is no upper bound.
+    Repeat {
+        /// The production to repeat.
+        production: Box<Production>,
+        /// The minimum number of repetitions.
+        min: u32,
+        /// The maximum number of repetitions, if any.
+        max: Option<u32>,
+    },
+}
+
+/// A context-free grammar, usable as a strategy which generates sentences of
+/// the grammar.
+///
+/// See the module documentation for details.
+#[derive(Clone, Debug)]
+pub struct Grammar {
+    compiled: Rc<Compiled>,
+    max_depth: u32,
+    max_size: u32,
+    max_repeat: u32,
+}
+
+#[derive(Debug)]
+struct Compiled {
+    names: Vec<String>,
+    rules: Vec<Expr>,
+    regexes: Vec<RegexGeneratorStrategy<String>>,
+    /// The cost of the shortest expansion of each rule.
+    costs: Vec<Cost>,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(String),
+    Regex(usize),
+    Rule(usize),
+    Sequence(Vec<Expr>),
+    Choice(Vec<Expr>),
+    Repeat(Box<Expr>, u32, Option<u32>),
+}
+
+/// The cost of an expansion, as the number of characters of literal text
+/// (counting regexes as one character) and the depth of rules.
+type Cost = (u64, u32);
+
+const INFINITE: Cost = (u64::MAX, u32::MAX);
+
+impl Grammar {
+    /// Builds a grammar from the given rules. The first rule is the one
+    /// generated.
+    pub fn new(rules: Vec<(String, Production)>) -> Result<Self, Error> {
+        if rules.is_empty() {
+            return Err(Error::NoRules);
+        }
+
+        let mut indices = HashMap::new();
+        for (ix, (name, _)) in rules.iter().enumerate() {
+            if indices.insert(name.clone(), ix).is_some() {
+                return Err(Error::DuplicateRule(name.clone()));
+            }
+        }
+
+        let mut regexes = vec![];
+        let compiled_rules = rules.iter()
+            .map(|(_, production)|
+                 compile(production, &indices, &mut regexes))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut compiled = Compiled {
+            names: rules.into_iter().map(|(name, _)| name).collect(),
+            rules: compiled_rules,
+            regexes,
+            costs: vec![],
+        };
+        compiled.costs = compiled.shortest_costs();
+        if let Some(ix) = compiled.costs.iter().position(|&c| INFINITE == c) {
+            return Err(Error::InfiniteRule(compiled.names[ix].clone()));
+        }
+
+        Ok(Grammar {
+            compiled: Rc::new(compiled),
+            max_depth: 8,
+            max_size: 64,
+            max_repeat: 8,
+        })
+    }
+
+    /// Parses a grammar written in the notation described in the module
+    /// documentation.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        Grammar::new(Parser { text, offset: 0 }.grammar()?)
+    }
+
+    /// Returns a grammar which nests at most `max_depth` rules within each
+    /// other and expands at most `max_size` rules in total, other than in
+    /// the shortest expansions used once the limits are reached.
+    ///
+    /// The defaults are a depth of 8 and a size of 64.
+    pub fn with_limits(self, max_depth: u32, max_size: u32) -> Self {
+        Grammar { max_depth, max_size, .. self }
+    }
+
+    /// Returns a grammar which repeats productions without an upper bound,
+    /// such as `{ ... }`, at most `max_repeat` times more than their
+    /// minimum.
+    ///
+    /// The default is 8.
+    pub fn with_max_repeat(self, max_repeat: u32) -> Self {
+        Grammar { max_repeat, .. self }
+    }
+}
+
+fn compile(production: &Production, indices: &HashMap<String, usize>,
+           regexes: &mut Vec<RegexGeneratorStrategy<String>>)
+           -> Result<Expr, Error> {
+    let compile_all = |productions: &[Production],
+                       regexes: &mut Vec<RegexGeneratorStrategy<String>>|
+        productions.iter().map(|p| compile(p, indices, regexes))
+        .collect::<Result<Vec<_>, _>>();
+
+    Ok(match *production {
+        Production::Literal(ref text) => Expr::Literal(text.clone()),
+        Production::Regex(ref regex) => {
+            regexes.push(string::string_regex(regex).map_err(
+                |err| Error::Regex(regex.clone(), err))?);
+            Expr::Regex(regexes.len() - 1)
+        },
+        Production::Rule(ref name) => Expr::Rule(
+            *indices.get(name).ok_or_else(
+                || Error::UndefinedRule(name.clone()))?),
+        Production::Sequence(ref productions) =>
+            Expr::Sequence(compile_all(productions, regexes)?),
+        Production::Choice(ref productions) =>
+            Expr::Choice(compile_all(productions, regexes)?),
+        Production::Optional(ref production) => Expr::Repeat(
+            Box::new(compile(production, indices, regexes)?), 0, Some(1)),
+        Production::Repeat { ref production, min, max } => Expr::Repeat(
+            Box::new(compile(production, indices, regexes)?), min, max),
+    })
+}
+
+impl Compiled {
+    /// Computes the cost of the shortest expansion of each rule.
+    fn shortest_costs(&self) -> Vec<Cost> {
+        let mut costs = vec![INFINITE; self.rules.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for ix in 0..self.rules.len() {
+                let (len, depth) = cost(&self.rules[ix], &costs);
+                let new = (len, depth.saturating_add(1));
+                if new < costs[ix] {
+                    costs[ix] = new;
+                    changed = true;
+                }
+            }
+        }
+        costs
+    }
+
+    /// Returns the shortest expansion of the given rule, using
+    /// `minimal_regexes` as the text for regexes.
+    fn shortest_rule(&self, rule: usize, minimal_regexes: &[String]) -> Node {
+        let mut children = vec![];
+        self.shortest(&self.rules[rule], minimal_regexes, &mut children);
+        Node::Rule { rule, shortest: true, children }
+    }
+
+    fn shortest(&self, expr: &Expr, minimal_regexes: &[String],
+                out: &mut Vec<Node>) {
+        match *expr {
+            Expr::Literal(ref text) => out.push(Node::Text(text.clone())),
+            Expr::Regex(ix) =>
+                out.push(Node::Text(minimal_regexes[ix].clone())),
+            Expr::Rule(rule) =>
+                out.push(self.shortest_rule(rule, minimal_regexes)),
+            Expr::Sequence(ref exprs) => for expr in exprs {
+                self.shortest(expr, minimal_regexes, out);
+            },
+            Expr::Choice(ref exprs) => {
+                let best = exprs.iter().min_by_key(
+                    |expr| cost(expr, &self.costs)).expect("empty choice");
+                self.shortest(best, minimal_regexes, out);
+            },
+            Expr::Repeat(ref expr, min, _) => for _ in 0..min {
+                self.shortest(expr, minimal_regexes, out);
+            },
+        }
+    }
+}
+
+/// Returns the cost of the shortest expansion of `expr`, given those of the
+/// rules.
+fn cost(expr: &Expr, rules: &[Cost]) -> Cost {
+    let add = |(a_len, a_depth): Cost, (b_len, b_depth): Cost|
+        (a_len.saturating_add(b_len), a_depth.max(b_depth));
+
+    match *expr {
+        Expr::Literal(ref text) => (text.chars().count() as u64, 0),
+        Expr::Regex(_) => (1, 0),
+        Expr::Rule(rule) => rules[rule],
+        Expr::Sequence(ref exprs) => exprs.iter().fold(
+            (0, 0), |accum, expr| add(accum, cost(expr, rules))),
+        Expr::Choice(ref exprs) => exprs.iter().map(|expr| cost(expr, rules))
+            .min().unwrap_or(INFINITE),
+        Expr::Repeat(_, 0, _) => (0, 0),
+        Expr::Repeat(ref expr, min, _) => {
+            let (len, depth) = cost(expr, rules);
+            (len.saturating_mul(min as u64), depth)
+        },
+    }
+}
+
+impl Strategy for Grammar {
+    type Value = GrammarValueTree;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        // Used for the shortest expansions of rules, which are needed while
+        // shrinking when there is no `TestRunner` available.
+        let minimal_regexes = self.compiled.regexes.iter().map(|regex| {
+            let mut tree = regex.new_value(runner)?;
+            while tree.simplify() { }
+            Ok(tree.current())
+        }).collect::<Result<Vec<_>, String>>()?;
+
+        let root = {
+            let mut generator = Generator {
+                grammar: self,
+                runner,
+                minimal_regexes: &minimal_regexes,
+                remaining: self.max_size,
+            };
+            generator.rule(0, 0)?
+        };
+
+        Ok(GrammarValueTree {
+            compiled: Rc::clone(&self.compiled),
+            minimal_regexes: Rc::new(minimal_regexes),
+            root,
+            next_rule: 0,
+            prev_rule: None,
+            next_regex: 0,
+            shrinking_regex: false,
+        })
+    }
+}
+
+struct Generator<'a> {
+    grammar: &'a Grammar,
+    runner: &'a mut TestRunner,
+    minimal_regexes: &'a [String],
+    /// The number of rules which may still be expanded.
+    remaining: u32,
+}
+
+impl<'a> Generator<'a> {
+    fn rule(&mut self, rule: usize, depth: u32) -> Result<Node, String> {
+        let compiled = &self.grammar.compiled;
+        if depth >= self.grammar.max_depth || 0 == self.remaining {
+            return Ok(compiled.shortest_rule(rule, self.minimal_regexes));
+        }
+
+        self.remaining -= 1;
+        let mut children = vec![];
+        self.expand(&compiled.rules[rule], depth + 1, &mut children)?;
+        Ok(Node::Rule { rule, shortest: false, children })
+    }
+
+    fn expand(&mut self, expr: &Expr, depth: u32, out: &mut Vec<Node>)
+              -> Result<(), String> {
+        let compiled = &self.grammar.compiled;
+        match *expr {
+            Expr::Literal(ref text) => out.push(Node::Text(text.clone())),
+            Expr::Regex(ix) => out.push(Node::Regex(
+                compiled.regexes[ix].new_value(self.runner)?)),
+            Expr::Rule(rule) => out.push(self.rule(rule, depth)?),
+            Expr::Sequence(ref exprs) => for expr in exprs {
+                self.expand(expr, depth, out)?;
+            },
+            Expr::Choice(ref exprs) => {
+                // Alternatives which can never finish, such as empty
+                // choices, are never taken.
+                let finite = exprs.iter()
+                    .filter(|expr| INFINITE != cost(expr, &compiled.costs))
+                    .collect::<Vec<_>>();
+                let ix = self.runner.rng().gen_range(0, finite.len());
+                self.expand(finite[ix], depth, out)?;
+            },
+            Expr::Repeat(ref expr, min, max) => {
+                let max = if INFINITE == cost(expr, &compiled.costs) {
+                    min
+                } else {
+                    max.unwrap_or(min.saturating_add(self.grammar.max_repeat))
+                        .max(min)
+                };
+                let count = self.runner.rng().gen_range(
+                    min as u64, max as u64 + 1);
+                for i in 0..count {
+                    // Don't add optional repetitions once out of budget,
+                    // since each would just add another shortest expansion.
+                    if i >= min as u64 && 0 == self.remaining {
+                        break;
+                    }
+                    self.expand(expr, depth, out)?;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// A node in the derivation of a value.
+enum Node {
+    Text(String),
+    Regex(RegexGeneratorValueTree<String>),
+    Rule {
+        rule: usize,
+        /// Whether this is the shortest expansion of the rule.
+        shortest: bool,
+        children: Vec<Node>,
+    },
+}
+
+impl Node {
+    fn write(&self, out: &mut String) {
+        match *self {
+            Node::Text(ref text) => out.push_str(text),
+            Node::Regex(ref tree) => out.push_str(&tree.current()),
+            Node::Rule { ref children, .. } =>
+                for child in children { child.write(out); },
+        }
+    }
+
+    /// Returns the rule node with index `*n` in pre-order, decrementing `*n`
+    /// for each rule node passed.
+    fn nth_rule(&mut self, n: &mut usize) -> Option<&mut Node> {
+        if let Node::Rule { .. } = *self {
+            if 0 == *n {
+                return Some(self);
+            }
+            *n -= 1;
+        }
+
+        if let Node::Rule { ref mut children, .. } = *self {
+            for child in children {
+                if let Some(found) = child.nth_rule(n) {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+
+    /// Like `nth_rule()`, but for the regex leaves.
+    fn nth_regex(&mut self, n: &mut usize)
+                 -> Option<&mut RegexGeneratorValueTree<String>> {
+        match *self {
+            Node::Text(_) => None,
+            Node::Regex(ref mut tree) => if 0 == *n {
+                Some(tree)
+            } else {
+                *n -= 1;
+                None
+            },
+            Node::Rule { ref mut children, .. } => {
+                for child in children {
+                    if let Some(found) = child.nth_regex(n) {
+                        return Some(found);
+                    }
+                }
+                None
+            },
+        }
+    }
+}
+
+/// `ValueTree` corresponding to `Grammar`.
+pub struct GrammarValueTree {
+    compiled: Rc<Compiled>,
+    minimal_regexes: Rc<Vec<String>>,
+    root: Node,
+    /// The pre-order index of the next rule node to replace with its shortest
+    /// expansion.
+    next_rule: usize,
+    /// The index of the rule node most recently replaced and its original
+    /// value.
+    prev_rule: Option<(usize, Node)>,
+    /// The index of the regex leaf currently being shrunk.
+    next_regex: usize,
+    /// Whether the last call to `simplify()` simplified a regex leaf.
+    shrinking_regex: bool,
+}
+
+impl fmt::Debug for GrammarValueTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GrammarValueTree")
+            .field("current", &self.current())
+            .field("next_rule", &self.next_rule)
+            .field("next_regex", &self.next_regex)
+            .finish()
+    }
+}
+
+impl ValueTree for GrammarValueTree {
+    type Value = String;
+
+    fn current(&self) -> String {
+        let mut out = String::new();
+        self.root.write(&mut out);
+        out
+    }
+
+    fn simplify(&mut self) -> bool {
+        self.prev_rule = None;
+        self.shrinking_regex = false;
+
+        loop {
+            let mut n = self.next_rule;
+            let node = match self.root.nth_rule(&mut n) {
+                Some(node) => node,
+                None => break,
+            };
+
+            let rule = match *node {
+                Node::Rule { rule, shortest: false, .. } => rule,
+                _ => {
+                    self.next_rule += 1;
+                    continue;
+                },
+            };
+            let shortest = self.compiled.shortest_rule(
+                rule, &self.minimal_regexes);
+            self.prev_rule = Some((self.next_rule, mem::replace(
+                node, shortest)));
+            return true;
+        }
+
+        loop {
+            let mut n = self.next_regex;
+            match self.root.nth_regex(&mut n) {
+                Some(tree) => if tree.simplify() {
+                    self.shrinking_regex = true;
+                    return true;
+                },
+                None => return false,
+            }
+            self.next_regex += 1;
+        }
+    }
+
+    fn complicate(&mut self) -> bool {
+        if let Some((ix, original)) = self.prev_rule.take() {
+            let mut n = ix;
+            *self.root.nth_rule(&mut n).expect("replaced rule missing") =
+                original;
+            // The shortest expansion was too simple, so try simplifying
+            // within the original instead.
+            self.next_rule = ix + 1;
+            return true;
+        }
+
+        if self.shrinking_regex {
+            let mut n = self.next_regex;
+            if let Some(tree) = self.root.nth_regex(&mut n) {
+                return tree.complicate();
+            }
+        }
+        false
+    }
+}
+
+/// Parser for the notation described in the module documentation.
+struct Parser<'a> {
+    text: &'a str,
+    /// The byte offset of the next character to parse.
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek();
+        if let Some(ch) = ch {
+            self.offset += ch.len_utf8();
+        }
+        ch
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.offset += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error<T>(&self, message: &'static str) -> Result<T, Error> {
+        Err(Error::Syntax(self.offset, message))
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_space(&mut self) -> Result<(), Error> {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with('#') {
+                self.offset += rest.find('\n').unwrap_or(rest.len());
+            } else if rest.starts_with("(*") {
+                match rest.find("*)") {
+                    Some(end) => self.offset += end + 2,
+                    None => return self.error("unterminated comment"),
+                }
+            } else if rest.starts_with(char::is_whitespace) {
+                self.bump();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn grammar(mut self) -> Result<Vec<(String, Production)>, Error> {
+        let mut rules = vec![];
+        self.skip_space()?;
+        while self.peek().is_some() {
+            let name = match self.name()? {
+                Some(name) => name,
+                None => return self.error("expected rule name"),
+            };
+            self.skip_space()?;
+            if !self.definition() {
+                return self.error("expected `=` after rule name");
+            }
+            let production = self.choice()?;
+            self.skip_space()?;
+            self.eat(";");
+            self.skip_space()?;
+            rules.push((name, production));
+        }
+        Ok(rules)
+    }
+
+    fn definition(&mut self) -> bool {
+        self.eat("::=") || self.eat("=") || self.eat(":")
+    }
+
+    fn name(&mut self) -> Result<Option<String>, Error> {
+        let is_name_char = |ch: char| ch.is_alphanumeric() || '_' == ch ||
+            '-' == ch;
+
+        let rest = self.rest();
+        if rest.starts_with('<') {
+            match rest.find('>') {
+                Some(end) => {
+                    self.offset += end + 1;
+                    Ok(Some(rest[1..end].trim().to_owned()))
+                },
+                None => self.error("unterminated rule name"),
+            }
+        } else if rest.starts_with(|ch: char| ch.is_alphabetic() || '_' == ch) {
+            let end = rest.find(|ch| !is_name_char(ch)).unwrap_or(rest.len());
+            self.offset += end;
+            Ok(Some(rest[..end].to_owned()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns whether the next thing to parse is the start of a new rule.
+    fn at_rule(&mut self) -> Result<bool, Error> {
+        let offset = self.offset;
+        let at_rule = self.name()?.is_some() && {
+            self.skip_space()?;
+            self.definition()
+        };
+        self.offset = offset;
+        Ok(at_rule)
+    }
+
+    fn choice(&mut self) -> Result<Production, Error> {
+        let mut choices = vec![self.sequence()?];
+        while self.eat("|") {
+            choices.push(self.sequence()?);
+        }
+
+        Ok(if 1 == choices.len() {
+            choices.pop().expect("no choices")
+        } else {
+            Production::Choice(choices)
+        })
+    }
+
+    fn sequence(&mut self) -> Result<Production, Error> {
+        let mut items = vec![];
+        loop {
+            self.skip_space()?;
+            match self.peek() {
+                None | Some('|') | Some(')') | Some(']') | Some('}') |
+                Some(';') => break,
+                _ if self.at_rule()? => break,
+                _ => items.push(self.item()?),
+            }
+            self.skip_space()?;
+            self.eat(",");
+        }
+
+        Ok(if 1 == items.len() {
+            items.pop().expect("no items")
+        } else {
+            Production::Sequence(items)
+        })
+    }
+
+    fn item(&mut self) -> Result<Production, Error> {
+        let primary = self.primary()?;
+        self.skip_space()?;
+        Ok(if self.eat("?") {
+            Production::Optional(Box::new(primary))
+        } else if self.eat("*") {
+            Production::Repeat {
+                production: Box::new(primary), min: 0, max: None,
+            }
+        } else if self.eat("+") {
+            Production::Repeat {
+                production: Box::new(primary), min: 1, max: None,
+            }
+        } else {
+            primary
+        })
+    }
+
+    fn primary(&mut self) -> Result<Production, Error> {
+        let closing = |parser: &mut Self, close: &str| {
+            parser.skip_space()?;
+            if parser.eat(close) {
+                Ok(())
+            } else {
+                parser.error("unclosed bracket")
+            }
+        };
+
+        if let Some(name) = self.name()? {
+            return Ok(Production::Rule(name));
+        }
+
+        match self.bump() {
+            Some(quote @ '"') | Some(quote @ '\'') =>
+                self.literal(quote).map(Production::Literal),
+            Some('/') => self.regex().map(Production::Regex),
+            Some('(') => {
+                let production = self.choice()?;
+                closing(self, ")")?;
+                Ok(production)
+            },
+            Some('[') => {
+                let production = self.choice()?;
+                closing(self, "]")?;
+                Ok(Production::Optional(Box::new(production)))
+            },
+            Some('{') => {
+                let production = self.choice()?;
+                closing(self, "}")?;
+                Ok(Production::Repeat {
+                    production: Box::new(production), min: 0, max: None,
+                })
+            },
+            Some(ch) => {
+                self.offset -= ch.len_utf8();
+                self.error("unexpected character")
+            },
+            None => self.error("unexpected end of input"),
+        }
+    }
+
+    fn literal(&mut self, quote: char) -> Result<String, Error> {
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                None => return self.error("unterminated literal"),
+                Some(ch) if quote == ch => return Ok(text),
+                Some('\\') => text.push(match self.bump() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some(ch @ '\\') | Some(ch @ '"') | Some(ch @ '\'') => ch,
+                    _ => return self.error("invalid escape in literal"),
+                }),
+                Some(ch) => text.push(ch),
+            }
+        }
+    }
+
+    fn regex(&mut self) -> Result<String, Error> {
+        let mut regex = String::new();
+        loop {
+            match self.bump() {
+                None => return self.error("unterminated regex"),
+                Some('/') => return Ok(regex),
+                Some('\\') if self.eat("/") => regex.push('/'),
+                Some('\\') => {
+                    regex.push('\\');
+                    if let Some(ch) = self.bump() {
+                        regex.push(ch);
+                    }
+                },
+                Some(ch) => regex.push(ch),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static JSON: &str = r#"
+        json    = value ;
+        value   = object | array | string | number
+                | "true" | "false" | "null" ;
+        object  = "{" [ member { "," member } ] "}" ;
+        member  = string ":" value ;
+        array   = "[" [ value { "," value } ] "]" ;
+        string  = '"' /[a-z]{0,4}/ '"' ;
+        number  = /-?[1-9][0-9]{0,2}/ ;
+    "#;
+
+    /// Checks that `s` is valid under the `JSON` grammar, returning the rest
+    /// of the input after one value.
+    fn json_value(s: &str) -> Option<&str> {
+        let s = s.trim_start();
+        if s.starts_with('{') || s.starts_with('[') {
+            let close = if s.starts_with('{') { '}' } else { ']' };
+            let mut rest = &s[1..];
+            if rest.starts_with(close) {
+                return Some(&rest[1..]);
+            }
+            loop {
+                if '}' == close {
+                    rest = json_value(rest)?;
+                    if !rest.starts_with(':') { return None; }
+                    rest = &rest[1..];
+                }
+                rest = json_value(rest)?;
+                if rest.starts_with(close) {
+                    return Some(&rest[1..]);
+                } else if rest.starts_with(',') {
+                    rest = &rest[1..];
+                } else {
+                    return None;
+                }
+            }
+        } else if let Some(rest) = s.strip_prefix('"') {
+            let end = rest.find('"')?;
+            Some(&rest[end + 1..])
+        } else {
+            let end = s.find(|c: char| !c.is_alphanumeric() && '-' != c)
+                .unwrap_or(s.len());
+            if 0 == end { None } else { Some(&s[end..]) }
+        }
+    }
+
+    #[test]
+    fn generates_valid_sentences() {
+        let grammar = Grammar::parse(JSON).unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        let mut nested = 0;
+        for _ in 0..256 {
+            let mut tree = grammar.new_value(&mut runner).unwrap();
+            if tree.current().contains("[{") || tree.current().contains("[[") {
+                nested += 1;
+            }
+            loop {
+                let s = tree.current();
+                assert_eq!(Some(""), json_value(&s), "Invalid JSON {:?}", s);
+                if !tree.simplify() { break; }
+            }
+        }
+        assert!(nested > 0);
+    }
+
+    #[test]
+    fn respects_size_limit() {
+        let grammar = Grammar::parse(r#"
+            list = "(" { list } ")" ;
+        "#).unwrap().with_limits(100, 16);
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let s = grammar.new_value(&mut runner).unwrap().current();
+            assert!(s.matches('(').count() <= 16, "Too big: {}", s);
+        }
+    }
+
+    #[test]
+    fn respects_depth_limit() {
+        let grammar = Grammar::parse(r#"
+            nest = "(" nest ")" | "(" nest ")" | "x" ;
+        "#).unwrap().with_limits(4, 1000);
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let s = grammar.new_value(&mut runner).unwrap().current();
+            assert!(s.len() <= 9, "Too deep: {}", s);
+        }
+    }
+
+    #[test]
+    fn shrinks_to_shortest_expansions() {
+        let grammar = Grammar::parse(JSON).unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let mut tree = grammar.new_value(&mut runner).unwrap();
+            // Pretend that any value containing "[" fails
+            if !tree.current().contains('[') { continue; }
+
+            while tree.simplify() {
+                if !tree.current().contains('[') {
+                    tree.complicate();
+                }
+            }
+            // Repetitions are never removed, but everything other than a
+            // single array should be gone.
+            let s = tree.current();
+            assert_eq!(1, s.matches('[').count(), "Not minimal: {}", s);
+            assert!(s.contains("[]"), "Not minimal: {}", s);
+        }
+    }
+
+    #[test]
+    fn shrinks_regexes() {
+        let grammar = Grammar::parse(r"word = /[a-z]{3,8}/ ;").unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        let mut tree = grammar.new_value(&mut runner).unwrap();
+        while tree.simplify() { }
+        assert_eq!("aaa", tree.current());
+    }
+
+    #[test]
+    fn built_in_rust() {
+        let grammar = Grammar::new(vec![
+            ("greeting".to_owned(), Production::Sequence(vec![
+                Production::Choice(vec![
+                    Production::Literal("hello".to_owned()),
+                    Production::Literal("hi".to_owned()),
+                ]),
+                Production::Repeat {
+                    production: Box::new(Production::Rule("bang".to_owned())),
+                    min: 1,
+                    max: Some(3),
+                },
+            ])),
+            ("bang".to_owned(), Production::Literal("!".to_owned())),
+        ]).unwrap();
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let s = grammar.new_value(&mut runner).unwrap().current();
+            assert!(s.starts_with("h") && s.ends_with("!") &&
+                    s.matches('!').count() <= 3, "Unexpected {}", s);
+        }
+    }
+
+    #[test]
+    fn notation_variants() {
+        let grammar = Grammar::parse(r#"
+            (* BNF-style names and definitions *)
+            <digits> ::= <digit> <digit>*
+            <digit> ::= "0" | '1'   # comment
+            sign : ["-"] , "+"? ;
+        "#).unwrap();
+        assert_eq!(3, grammar.compiled.rules.len());
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let s = grammar.new_value(&mut runner).unwrap().current();
+            assert!(!s.is_empty() && s.chars().all(|c| '0' == c || '1' == c));
+        }
+    }
+
+    #[test]
+    fn invalid_grammars() {
+        match Grammar::parse("") {
+            Err(Error::NoRules) => (),
+            r => panic!("Unexpected {:?}", r),
+        }
+        match Grammar::parse("a = b ;") {
+            Err(Error::UndefinedRule(ref name)) if "b" == name => (),
+            r => panic!("Unexpected {:?}", r),
+        }
+        match Grammar::parse("a = 'x' ; a = 'y' ;") {
+            Err(Error::DuplicateRule(ref name)) if "a" == name => (),
+            r => panic!("Unexpected {:?}", r),
+        }
+        match Grammar::parse("a = 'x' a ;") {
+            Err(Error::InfiniteRule(ref name)) if "a" == name => (),
+            r => panic!("Unexpected {:?}", r),
+        }
+        match Grammar::parse("a = /[/ ;") {
+            Err(Error::Regex(..)) => (),
+            r => panic!("Unexpected {:?}", r),
+        }
+        match Grammar::parse("a = ( 'x' ;") {
+            Err(Error::Syntax(..)) => (),
+            r => panic!("Unexpected {:?}", r),
+        }
+        match Grammar::parse("a = 'x") {
+            Err(Error::Syntax(..)) => (),
+            r => panic!("Unexpected {:?}", r),
+        }
+        match Grammar::parse("a = 'x' \u{2192} ;") {
+            Err(Error::Syntax(8, "unexpected character")) => (),
+            r => panic!("Unexpected {:?}", r),
+        }
+    }
+}
@@ -347,7 +347,10 @@
 //! input space to a test. However, time is not infinite, so only a randomly
 //! sampled portion of the input space can be explored. This means that
 //! property testing is extremely unlikely to find single-value edge cases in a
-//! large space. For example, the following test will virtually always pass:
+//! large space. Proptest does go out of its way to try well-known edge cases
+//! such as zero and the minimum and maximum values, but anything more specific
+//! than that is left to chance. For example, the following test will virtually
+//! always pass:
 //!
 //! ```rust
 //! #[macro_use] extern crate proptest;
@@ -357,12 +360,12 @@
 //!     # /* NOREADME
 //!     #[test]
 //!     # NOREADME */
-//!     fn i64_abs_is_never_negative(a in prop::num::i64::ANY) {
-//!         assert!(a.abs() >= 0);
+//!     fn i64_is_never_magic(a in prop::num::i64::ANY) {
+//!         assert!(a != 0x1234_5678_9abc);
 //!     }
 //! }
 //! # // NOREADME
-//! # fn main() { i64_abs_is_never_negative(); } // NOREADME
+//! # fn main() { i64_is_never_magic(); } // NOREADME
 //! ```
 //!
 //! Because of this, traditional unit testing with intelligently selected cases
@@ -1297,9 +1300,11 @@
 extern crate bit_set;
 #[macro_use] extern crate quick_error;
 extern crate rand;
+extern crate regex;
 extern crate regex_syntax;
 
-#[cfg(test)] extern crate regex;
+#[cfg(test)] extern crate unicode_normalization;
+#[cfg(test)] extern crate unicode_segmentation;
 
 // Pervasive internal sugar
 macro_rules! mapfn {
@@ -1370,11 +1375,15 @@ pub mod strategy;
 pub mod bool;
 pub mod num;
 pub mod bits;
+pub mod bytes;
 pub mod tuple;
 pub mod array;
 pub mod collection;
 pub mod char;
 pub mod string;
+pub mod path;
+pub mod text;
+pub mod grammar;
 pub mod option;
 pub mod result;
 
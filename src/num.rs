@@ -10,36 +10,97 @@
 //! Strategies to generate numeric values (as opposed to integers used as bit
 //! fields).
 //!
-//! All strategies in this module shrink by binary searching towards 0.
+//! Each numeric type module provides `ANY` and `FULL`, and `Strategy` is
+//! implemented for `Range`, `RangeInclusive`, `RangeFrom`, `RangeTo` and
+//! `RangeToInclusive` of each type. The integer modules, which include
+//! `i128` and `u128`, also provide `NONZERO` to generate the corresponding
+//! `std::num::NonZero*` type.
+//!
+//! Integer strategies sample uniformly, except that with probability
+//! `Config::edge_bias` they instead pick a value likely to expose bugs, such
+//! as a bound of the range, zero, one or a power of two. Set it to 0 to sample
+//! uniformly.
+//!
+//! The float modules additionally provide flags such as `POSITIVE`, `NORMAL`
+//! and `QUIET_NAN` which can be combined with `|` to generate values from
+//! particular classes of floats, such as NaN, infinities, subnormals and
+//! signed zeros. Their `ANY` combines all of these except signalling NaN.
+//! `log_uniform()` generates floats within a range spread evenly across
+//! orders of magnitude.
+//!
+//! All strategies in this module shrink by binary searching towards 0, or
+//! towards whichever bound of the range is closest to 0 if the range does not
+//! contain 0. The integer modules also provide `toward()` for values which
+//! should instead shrink towards some other target.
+//!
+//! `wrapping()` and `saturating()` wrap the values of any of these strategies
+//! in `Wrapping` and `Saturating` respectively.
+//!
+//! The `distribution` module provides strategies for common non-uniform
+//! distributions, such as the normal and Zipf distributions.
 
-macro_rules! numeric_api {
-    ($typ:ident, $epsilon:expr) => {
-        /// Type of the `ANY` constant.
-        #[derive(Clone, Copy, Debug)]
-        pub struct Any(());
-        /// Generates integers with completely arbitrary values, uniformly
-        /// distributed over the whole range.
-        pub const ANY: Any = Any(());
+use std::fmt;
+use std::num::{Saturating, Wrapping};
 
-        impl Strategy for Any {
+use rand::{self, Rng};
+use rand::distributions::IndependentSample;
+
+use strategy::*;
+use test_runner::*;
+
+pub mod distribution;
+
+/// Uniformly samples an integer from 0 to `span`, both inclusive.
+///
+/// `rand` cannot sample 128-bit integers on stable Rust, so the integer
+/// strategies all go through this instead.
+fn sample_uniform<R : Rng>(rng: &mut R, span: u128) -> u128 {
+    fn next_u128<R : Rng>(rng: &mut R) -> u128 {
+        ((rng.next_u64() as u128) << 64) | rng.next_u64() as u128
+    }
+
+    if span < ::std::u64::MAX as u128 {
+        rand::distributions::Range::new(0, span as u64 + 1)
+            .ind_sample(rng) as u128
+    } else if ::std::u64::MAX as u128 == span {
+        rng.next_u64() as u128
+    } else if ::std::u128::MAX == span {
+        next_u128(rng)
+    } else {
+        // Reject values from the incomplete copy of the range at the top so
+        // that every value is equally likely.
+        let count = span + 1;
+        let zone = ::std::u128::MAX - (::std::u128::MAX - span) % count;
+        loop {
+            let value = next_u128(rng);
+            if value <= zone {
+                return value % count;
+            }
+        }
+    }
+}
+
+macro_rules! numeric_api {
+    ($typ:ident, $below:expr) => {
+        impl Strategy for Range<$typ> {
             type Value = BinarySearch;
 
             fn new_value(&self, runner: &mut TestRunner)
                          -> Result<BinarySearch, String> {
-                Ok(BinarySearch::new(runner.rng().gen()))
+                Ok(BinarySearch::new_clamped(
+                    self.start, sample_range(runner, self.start, self.end),
+                    ($below)(self.end)))
             }
         }
 
-        impl Strategy for Range<$typ> {
+        impl Strategy for RangeInclusive<$typ> {
             type Value = BinarySearch;
 
             fn new_value(&self, runner: &mut TestRunner)
                          -> Result<BinarySearch, String> {
-                let range = rand::distributions::Range::new(
-                    self.start, self.end);
+                let (start, end) = (*self.start(), *self.end());
                 Ok(BinarySearch::new_clamped(
-                    self.start, range.ind_sample(runner.rng()),
-                    self.end-$epsilon))
+                    start, sample_range_inclusive(runner, start, end), end))
             }
         }
 
@@ -48,12 +109,10 @@ macro_rules! numeric_api {
 
             fn new_value(&self, runner: &mut TestRunner)
                          -> Result<BinarySearch, String> {
-                // TODO `rand` has no way to express the inclusive-end range we
-                // need here.
-                let range = rand::distributions::Range::new(
-                    self.start, ::std::$typ::MAX);
                 Ok(BinarySearch::new_clamped(
-                    self.start, range.ind_sample(runner.rng()),
+                    self.start,
+                    sample_range_inclusive(runner, self.start,
+                                           ::std::$typ::MAX),
                     ::std::$typ::MAX))
             }
         }
@@ -63,24 +122,300 @@ macro_rules! numeric_api {
 
             fn new_value(&self, runner: &mut TestRunner)
                          -> Result<BinarySearch, String> {
-                let range = rand::distributions::Range::new(
-                    ::std::$typ::MIN, self.end);
                 Ok(BinarySearch::new_clamped(
-                    ::std::$typ::MIN, range.ind_sample(runner.rng()),
+                    ::std::$typ::MIN,
+                    sample_range(runner, ::std::$typ::MIN, self.end),
+                    ($below)(self.end)))
+            }
+        }
+
+        impl Strategy for RangeToInclusive<$typ> {
+            type Value = BinarySearch;
+
+            fn new_value(&self, runner: &mut TestRunner)
+                         -> Result<BinarySearch, String> {
+                Ok(BinarySearch::new_clamped(
+                    ::std::$typ::MIN,
+                    sample_range_inclusive(runner, ::std::$typ::MIN,
+                                           self.end),
                     self.end))
             }
         }
+
+        /// Type of the `FULL` constant.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Full(());
+        /// Generates values anywhere from `MIN` to `MAX` inclusive.
+        ///
+        /// This is what `..` would mean as a strategy, which is not possible
+        /// since `RangeFull` does not say what type of value to produce.
+        pub const FULL: Full = Full(());
+
+        impl Strategy for Full {
+            type Value = BinarySearch;
+
+            fn new_value(&self, runner: &mut TestRunner)
+                         -> Result<BinarySearch, String> {
+                Ok(BinarySearch::new_clamped(
+                    ::std::$typ::MIN,
+                    sample_range_inclusive(runner, ::std::$typ::MIN,
+                                           ::std::$typ::MAX),
+                    ::std::$typ::MAX))
+            }
+        }
+    }
+}
+
+/// Defines `ANY` and the sampling functions used by `numeric_api!` for
+/// integer types.
+///
+/// With probability `Config::edge_bias`, a value is picked from the special
+/// values within the range instead of uniformly from the whole range.
+macro_rules! integer_sampling {
+    ($typ:ident, $negatives:expr) => {
+        /// Type of the `ANY` constant.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Any(());
+        /// Generates completely arbitrary values over the whole range of
+        /// the type.
+        pub const ANY: Any = Any(());
+
+        impl Strategy for Any {
+            type Value = BinarySearch;
+
+            fn new_value(&self, runner: &mut TestRunner)
+                         -> Result<BinarySearch, String> {
+                Ok(BinarySearch::new(sample_any(runner)))
+            }
+        }
+
+        fn sample_any(runner: &mut TestRunner) -> $typ {
+            sample_range_inclusive(runner, ::std::$typ::MIN, ::std::$typ::MAX)
+        }
+
+        fn sample_range(runner: &mut TestRunner, lo: $typ, hi: $typ) -> $typ {
+            assert!(lo < hi, "Invalid range {}..{}", lo, hi);
+            sample_range_inclusive(runner, lo, hi - 1)
+        }
+
+        fn sample_range_inclusive(runner: &mut TestRunner,
+                                  lo: $typ, hi: $typ) -> $typ {
+            assert!(lo <= hi, "Invalid range {}..={}", lo, hi);
+
+            let edge_bias = runner.config().edge_bias;
+            if edge_bias > 0.0 && runner.rng().next_f64() < edge_bias {
+                // The bounds and values around zero are the most interesting
+                // by far, so don't let the many powers of two drown them out.
+                return if runner.rng().gen() {
+                    edge_value(runner, lo, hi)
+                } else {
+                    special_value(runner, lo, hi)
+                };
+            }
+
+            // Sign extension cancels out in the subtraction, so this is the
+            // distance between the two for signed types as well.
+            let span = (hi as u128).wrapping_sub(lo as u128);
+            lo.wrapping_add(super::sample_uniform(runner.rng(), span) as $typ)
+        }
+
+        /// Returns one of the bounds themselves or whichever of 0, 1 and -1
+        /// are within `lo` to `hi` inclusive.
+        fn edge_value(runner: &mut TestRunner, lo: $typ, hi: $typ) -> $typ {
+            let values = [lo, hi, 0, 1, (1 as $typ).wrapping_neg()];
+            let count = if $negatives { 5 } else { 4 };
+            // The bounds are always in range, so this ends quickly.
+            loop {
+                let value = values[runner.rng().gen_range(0, count)];
+                if lo <= value && value <= hi {
+                    return value;
+                }
+            }
+        }
+
+        /// Returns a power of two or one of its neighbours, or for signed
+        /// types the negation of one of those, within `lo` to `hi`
+        /// inclusive.
+        ///
+        /// The candidates are picked directly rather than listed, since this
+        /// is called for every biased sample. If none within the range is
+        /// found after a few attempts, one of the bounds is returned instead.
+        pub(super) fn special_value(runner: &mut TestRunner,
+                                    lo: $typ, hi: $typ) -> $typ {
+            let bits = ::std::mem::size_of::<$typ>() as u32 * 8;
+            // No candidate built from a power of two greater than one more
+            // than the largest magnitude in the range can be within it.
+            let magnitude = if $negatives {
+                ::std::cmp::max((lo as i128).unsigned_abs(),
+                                (hi as i128).unsigned_abs())
+            } else {
+                hi as u128
+            };
+            let max_bit = ::std::cmp::min(
+                bits - 1, 127 - magnitude.saturating_add(1).leading_zeros());
+
+            for _ in 0..64 {
+                let power: $typ = 1 << runner.rng().gen_range(0, max_bit + 1);
+                let mut value = match runner.rng().gen_range(0, 3) {
+                    0 => power.wrapping_sub(1),
+                    1 => power,
+                    _ => power.wrapping_add(1),
+                };
+                if $negatives && runner.rng().gen() {
+                    value = value.wrapping_neg();
+                }
+
+                if lo <= value && value <= hi {
+                    return value;
+                }
+            }
+
+            if runner.rng().gen() { lo } else { hi }
+        }
+    }
+}
+
+/// Defines the `NONZERO` strategy for an integer type, which shrinks towards
+/// `$pos` or `$neg` depending on the sign of the initial value.
+macro_rules! nonzero_api {
+    ($typ:ident, $nonzero:ident, $pos:expr, $neg:expr) => {
+        /// Type of the `NONZERO` constant.
+        #[derive(Clone, Copy, Debug)]
+        pub struct NonZero(());
+        /// Generates arbitrary non-zero values, shrinking towards the
+        /// non-zero value closest to 0 with the same sign.
+        pub const NONZERO: NonZero = NonZero(());
+
+        /// `ValueTree` corresponding to `NONZERO`.
+        #[derive(Clone, Copy, Debug)]
+        pub struct NonZeroValueTree(BinarySearch);
+
+        impl Strategy for NonZero {
+            type Value = NonZeroValueTree;
+
+            fn new_value(&self, runner: &mut TestRunner)
+                         -> Result<NonZeroValueTree, String> {
+                loop {
+                    let start = sample_any(runner);
+                    if 0 != start {
+                        return Ok(NonZeroValueTree(BinarySearch::new_clamped(
+                            $pos, start, $neg)));
+                    }
+                }
+            }
+        }
+
+        impl ValueTree for NonZeroValueTree {
+            type Value = ::std::num::$nonzero;
+
+            fn current(&self) -> ::std::num::$nonzero {
+                ::std::num::$nonzero::new(self.0.current())
+                    .expect("Shrank non-zero value to zero")
+            }
+
+            fn simplify(&mut self) -> bool {
+                self.0.simplify()
+            }
+
+            fn complicate(&mut self) -> bool {
+                self.0.complicate()
+            }
+        }
+    }
+}
+
+/// Defines `toward()` for an integer type, whose values shrink towards an
+/// arbitrary target. `$unsigned` is the unsigned type of the same width, used
+/// to binary search on the distance from the target.
+macro_rules! toward_api {
+    ($typ:ident, $unsigned:ident) => {
+        /// Strategy returned by `toward()`.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Toward {
+            lo: $typ,
+            hi: $typ,
+            target: $typ,
+        }
+
+        /// Generates values from `range` which shrink towards `target`
+        /// instead of towards 0.
+        ///
+        /// This suits values whose simplest form is not 0, such as years,
+        /// port numbers or HTTP status codes. Values on either side of
+        /// `target` binary search their way towards it.
+        ///
+        /// ## Panics
+        ///
+        /// Panics if `target` is not within `range`.
+        pub fn toward(range: RangeInclusive<$typ>, target: $typ) -> Toward {
+            let (lo, hi) = (*range.start(), *range.end());
+            assert!(lo <= target && target <= hi,
+                    "Target {} not within {}..={}", target, lo, hi);
+            Toward { lo, hi, target }
+        }
+
+        impl Strategy for Toward {
+            type Value = TowardValueTree;
+
+            fn new_value(&self, runner: &mut TestRunner)
+                         -> Result<TowardValueTree, String> {
+                let start = sample_range_inclusive(runner, self.lo, self.hi);
+                let below = start < self.target;
+                // The distance always fits in the unsigned type, even when
+                // the difference would overflow `$typ`.
+                let distance = if below {
+                    (self.target as $unsigned).wrapping_sub(start as $unsigned)
+                } else {
+                    (start as $unsigned).wrapping_sub(self.target as $unsigned)
+                };
+
+                Ok(TowardValueTree {
+                    target: self.target,
+                    below,
+                    distance: super::$unsigned::BinarySearch::new(distance),
+                })
+            }
+        }
+
+        /// `ValueTree` corresponding to `Toward`.
+        #[derive(Clone, Copy, Debug)]
+        pub struct TowardValueTree {
+            target: $typ,
+            below: bool,
+            distance: super::$unsigned::BinarySearch,
+        }
+
+        impl ValueTree for TowardValueTree {
+            type Value = $typ;
+
+            fn current(&self) -> $typ {
+                let distance = self.distance.current() as $typ;
+                if self.below {
+                    self.target.wrapping_sub(distance)
+                } else {
+                    self.target.wrapping_add(distance)
+                }
+            }
+
+            fn simplify(&mut self) -> bool {
+                self.distance.simplify()
+            }
+
+            fn complicate(&mut self) -> bool {
+                self.distance.complicate()
+            }
+        }
     }
 }
 
 macro_rules! signed_integer_bin_search {
-    ($typ:ident) => {
+    ($typ:ident, $unsigned:ident, $nonzero:ident) => {
         #[allow(missing_docs)]
         pub mod $typ {
-            use std::ops::{Range, RangeFrom, RangeTo};
+            use std::ops::{Range, RangeFrom, RangeInclusive, RangeTo,
+                           RangeToInclusive};
 
-            use rand::{self, Rng};
-            use rand::distributions::IndependentSample;
+            use rand::Rng;
 
             use strategy::*;
             use test_runner::TestRunner;
@@ -104,13 +439,13 @@ macro_rules! signed_integer_bin_search {
                 }
 
                 /// Creates a new binary searcher which will not produce values
-                /// on the other side of `lo` or `hi` from `start`. `lo` is
-                /// inclusive, `hi` is exclusive.
+                /// on the other side of `lo` or `hi` from `start`. Both `lo`
+                /// and `hi` are inclusive.
                 fn new_clamped(lo: $typ, start: $typ, hi: $typ) -> Self {
                     use std::cmp::{min, max};
 
                     BinarySearch {
-                        lo: if start < 0 { min(0, hi-1) } else { max(0, lo) },
+                        lo: if start < 0 { min(0, hi) } else { max(0, lo) },
                         hi: start,
                         curr: start,
                     }
@@ -171,19 +506,22 @@ macro_rules! signed_integer_bin_search {
                 }
             }
 
-            numeric_api!($typ, 1);
+            integer_sampling!($typ, true);
+            nonzero_api!($typ, $nonzero, 1, -1);
+            toward_api!($typ, $unsigned);
+            numeric_api!($typ, |end: $typ| end - 1);
         }
     }
 }
 
 macro_rules! unsigned_integer_bin_search {
-    ($typ:ident) => {
+    ($typ:ident, $nonzero:ident) => {
         #[allow(missing_docs)]
         pub mod $typ {
-            use std::ops::{Range, RangeFrom, RangeTo};
+            use std::ops::{Range, RangeFrom, RangeInclusive, RangeTo,
+                           RangeToInclusive};
 
-            use rand::{self, Rng};
-            use rand::distributions::IndependentSample;
+            use rand::Rng;
 
             use strategy::*;
             use test_runner::TestRunner;
@@ -256,27 +594,35 @@ macro_rules! unsigned_integer_bin_search {
                 }
             }
 
-            numeric_api!($typ, 1);
+            integer_sampling!($typ, false);
+            nonzero_api!($typ, $nonzero, 1, 0);
+            toward_api!($typ, $typ);
+            numeric_api!($typ, |end: $typ| end - 1);
         }
     }
 }
 
-signed_integer_bin_search!(i8);
-signed_integer_bin_search!(i16);
-signed_integer_bin_search!(i32);
-signed_integer_bin_search!(i64);
-signed_integer_bin_search!(isize);
-unsigned_integer_bin_search!(u8);
-unsigned_integer_bin_search!(u16);
-unsigned_integer_bin_search!(u32);
-unsigned_integer_bin_search!(u64);
-unsigned_integer_bin_search!(usize);
+signed_integer_bin_search!(i8, u8, NonZeroI8);
+signed_integer_bin_search!(i16, u16, NonZeroI16);
+signed_integer_bin_search!(i32, u32, NonZeroI32);
+signed_integer_bin_search!(i64, u64, NonZeroI64);
+signed_integer_bin_search!(i128, u128, NonZeroI128);
+signed_integer_bin_search!(isize, usize, NonZeroIsize);
+unsigned_integer_bin_search!(u8, NonZeroU8);
+unsigned_integer_bin_search!(u16, NonZeroU16);
+unsigned_integer_bin_search!(u32, NonZeroU32);
+unsigned_integer_bin_search!(u64, NonZeroU64);
+unsigned_integer_bin_search!(u128, NonZeroU128);
+unsigned_integer_bin_search!(usize, NonZeroUsize);
 
 macro_rules! float_bin_search {
-    ($typ:ident) => {
+    ($typ:ident, $bits:ident, $signed_bits:ident,
+     $mantissa_bits:expr, $exponent_bits:expr) => {
         #[allow(missing_docs)]
         pub mod $typ {
-            use std::ops::{Range, RangeFrom, RangeTo};
+            use std::num::FpCategory;
+            use std::ops::{BitOr, BitOrAssign, Range, RangeFrom,
+                           RangeInclusive, RangeTo, RangeToInclusive};
 
             use rand::{self, Rng};
             use rand::distributions::IndependentSample;
@@ -284,6 +630,12 @@ macro_rules! float_bin_search {
             use strategy::*;
             use test_runner::TestRunner;
 
+            const SIGN_MASK: $bits = 1 << ($mantissa_bits + $exponent_bits);
+            const EXP_MASK: $bits =
+                ((1 << $exponent_bits) - 1) << $mantissa_bits;
+            const MANTISSA_MASK: $bits = (1 << $mantissa_bits) - 1;
+            const QUIET_MASK: $bits = 1 << ($mantissa_bits - 1);
+
             /// Shrinks a float towards 0, using binary search to find boundary
             /// points.
             ///
@@ -293,6 +645,7 @@ macro_rules! float_bin_search {
                 lo: $typ,
                 curr: $typ,
                 hi: $typ,
+                allowed: Any,
             }
             impl BinarySearch {
                 /// Creates a new binary searcher starting at the given value.
@@ -301,13 +654,15 @@ macro_rules! float_bin_search {
                         lo: 0.0,
                         curr: start,
                         hi: start,
+                        allowed: Any::ALL,
                     }
                 }
 
                 /// Creates a new binary searcher which will not produce values
-                /// on the other side of `lo` or `hi` from `start`. `lo` is
-                /// inclusive, `hi` is exclusive.
-                fn new_clamped(lo: $typ, start: $typ, hi: $typ) -> Self {
+                /// on the other side of `lo` or `hi` from `start`. Both `lo`
+                /// and `hi` are inclusive.
+                pub(crate) fn new_clamped(lo: $typ, start: $typ, hi: $typ)
+                                          -> Self {
                     BinarySearch {
                         lo: if start.is_sign_negative() {
                             hi.min(0.0)
@@ -316,6 +671,32 @@ macro_rules! float_bin_search {
                         },
                         hi: start,
                         curr: start,
+                        allowed: Any::ALL,
+                    }
+                }
+
+                /// Creates a new binary searcher which will only produce
+                /// values permitted by `allowed`, shrinking towards the
+                /// permitted value of the same sign closest to 0.
+                fn new_with_types(start: $typ, allowed: Any) -> Self {
+                    let lo = if allowed.contains(ZERO) {
+                        0.0
+                    } else if allowed.contains(SUBNORMAL) {
+                        $typ::from_bits(1)
+                    } else if allowed.contains(NORMAL) {
+                        ::std::$typ::MIN_POSITIVE
+                    } else {
+                        start.abs()
+                    };
+                    // Only shrink finite values towards a non-zero bound if
+                    // they are on the same side of it.
+                    let lo = if start.abs() < lo { start.abs() } else { lo };
+
+                    BinarySearch {
+                        lo: if start.is_sign_negative() { -lo } else { lo },
+                        curr: start,
+                        hi: start,
+                        allowed,
                     }
                 }
 
@@ -345,15 +726,8 @@ macro_rules! float_bin_search {
                 fn done(lo: $typ, hi: $typ) -> bool {
                     (lo.abs() > hi.abs() && !hi.is_nan()) || lo.is_nan()
                 }
-            }
-            impl ValueTree for BinarySearch {
-                type Value = $typ;
-
-                fn current(&self) -> $typ {
-                    self.curr
-                }
 
-                fn simplify(&mut self) -> bool {
+                fn simplify_once(&mut self) -> bool {
                     if BinarySearch::done(self.lo, self.hi) {
                         return false;
                     }
@@ -362,7 +736,7 @@ macro_rules! float_bin_search {
                     self.reposition()
                 }
 
-                fn complicate(&mut self) -> bool {
+                fn complicate_once(&mut self) -> bool {
                     if BinarySearch::done(self.lo, self.hi) {
                         return false;
                     }
@@ -375,21 +749,359 @@ macro_rules! float_bin_search {
 
                     self.reposition()
                 }
+
+                fn ensure_acceptable(&mut self) {
+                    while !self.allowed.permits(self.curr) {
+                        if !self.complicate_once() {
+                            panic!("Unable to complicate floating-point \
+                                    back to acceptable value");
+                        }
+                    }
+                }
+            }
+            impl ValueTree for BinarySearch {
+                type Value = $typ;
+
+                fn current(&self) -> $typ {
+                    self.curr
+                }
+
+                fn simplify(&mut self) -> bool {
+                    if self.simplify_once() {
+                        self.ensure_acceptable();
+                        true
+                    } else {
+                        false
+                    }
+                }
+
+                fn complicate(&mut self) -> bool {
+                    if self.complicate_once() {
+                        self.ensure_acceptable();
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+
+            /// Strategy which generates floats from particular classes.
+            ///
+            /// Values are combined with `|` to select a set of classes; for
+            /// example, `POSITIVE | NORMAL | SUBNORMAL | ZERO` produces any
+            /// finite value which is not negative. If neither `POSITIVE` nor
+            /// `NEGATIVE` is given, both signs are produced, and if no class
+            /// is given, every class other than NaN is produced.
+            ///
+            /// Generated values shrink towards the permitted value closest
+            /// to 0 which has the same sign. Infinities and NaNs immediately
+            /// try to become that finite value.
+            #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+            pub struct Any(u32);
+
+            /// Generates values whose sign bit is clear, including `0.0`,
+            /// positive infinity and NaNs with a clear sign bit.
+            pub const POSITIVE: Any = Any(1 << 0);
+            /// Generates values whose sign bit is set, including `-0.0`,
+            /// negative infinity and NaNs with a set sign bit.
+            pub const NEGATIVE: Any = Any(1 << 1);
+            /// Generates finite, non-zero values which are not subnormal.
+            pub const NORMAL: Any = Any(1 << 2);
+            /// Generates non-zero values too small to be represented as
+            /// normal values.
+            pub const SUBNORMAL: Any = Any(1 << 3);
+            /// Generates `0.0` and `-0.0`.
+            pub const ZERO: Any = Any(1 << 4);
+            /// Generates positive and negative infinity.
+            pub const INFINITE: Any = Any(1 << 5);
+            /// Generates quiet NaNs, with arbitrary payloads.
+            pub const QUIET_NAN: Any = Any(1 << 6);
+            /// Generates signalling NaNs, with arbitrary payloads.
+            ///
+            /// Note that some platforms convert signalling NaNs to quiet NaNs
+            /// when they are moved around.
+            pub const SIGNALING_NAN: Any = Any(1 << 7);
+
+            /// Generates values of every class other than signalling NaN,
+            /// with either sign.
+            pub const ANY: Any = Any(
+                POSITIVE.0 | NEGATIVE.0 | NORMAL.0 | SUBNORMAL.0 | ZERO.0 |
+                INFINITE.0 | QUIET_NAN.0);
+
+            // Relative likelihood of generating each class when it is
+            // permitted. Normal values are by far the most common so that
+            // tests still mostly see ordinary inputs.
+            const CLASS_WEIGHTS: [(Any, u32); 6] = [
+                (NORMAL, 20), (SUBNORMAL, 3), (ZERO, 3), (INFINITE, 2),
+                (QUIET_NAN, 2), (SIGNALING_NAN, 1),
+            ];
+
+            impl Any {
+                const ALL: Any = Any(0xFF);
+                const SIGNS: Any = Any(POSITIVE.0 | NEGATIVE.0);
+                const CLASSES: Any = Any(!Any::SIGNS.0 & Any::ALL.0);
+
+                fn contains(self, other: Any) -> bool {
+                    other.0 == (self.0 & other.0)
+                }
+
+                fn intersects(self, other: Any) -> bool {
+                    0 != (self.0 & other.0)
+                }
+
+                /// Returns the flags with the defaults for missing signs or
+                /// classes filled in.
+                fn normalised(self) -> Any {
+                    let mut flags = self;
+                    if !flags.intersects(Any::SIGNS) {
+                        flags |= Any::SIGNS;
+                    }
+                    if !flags.intersects(Any::CLASSES) {
+                        flags |= NORMAL | SUBNORMAL | ZERO | INFINITE;
+                    }
+                    flags
+                }
+
+                fn permits(self, value: $typ) -> bool {
+                    let sign = if value.is_sign_negative() {
+                        NEGATIVE
+                    } else {
+                        POSITIVE
+                    };
+                    let class = match value.classify() {
+                        FpCategory::Normal => NORMAL,
+                        FpCategory::Subnormal => SUBNORMAL,
+                        FpCategory::Zero => ZERO,
+                        FpCategory::Infinite => INFINITE,
+                        FpCategory::Nan =>
+                            if 0 != value.to_bits() & QUIET_MASK {
+                                QUIET_NAN
+                            } else {
+                                SIGNALING_NAN
+                            },
+                    };
+
+                    self.contains(sign | class)
+                }
             }
 
-            numeric_api!($typ, 0.0);
+            impl BitOr for Any {
+                type Output = Any;
+
+                fn bitor(self, rhs: Any) -> Any {
+                    Any(self.0 | rhs.0)
+                }
+            }
+
+            impl BitOrAssign for Any {
+                fn bitor_assign(&mut self, rhs: Any) {
+                    self.0 |= rhs.0;
+                }
+            }
+
+            impl Strategy for Any {
+                type Value = BinarySearch;
+
+                fn new_value(&self, runner: &mut TestRunner)
+                             -> Result<BinarySearch, String> {
+                    let flags = self.normalised();
+
+                    let total: u32 = CLASS_WEIGHTS.iter()
+                        .filter(|&&(class, _)| flags.contains(class))
+                        .map(|&(_, weight)| weight).sum();
+                    let mut pick = runner.rng().gen_range(0, total);
+                    let mut class = NORMAL;
+                    for &(c, weight) in CLASS_WEIGHTS.iter()
+                        .filter(|&&(c, _)| flags.contains(c))
+                    {
+                        if pick < weight {
+                            class = c;
+                            break;
+                        }
+                        pick -= weight;
+                    }
+
+                    let random: $bits = runner.rng().gen();
+                    let bits = if NORMAL == class {
+                        let exponent: $bits = runner.rng().gen_range(
+                            1, EXP_MASK >> $mantissa_bits);
+                        (exponent << $mantissa_bits) | (random & MANTISSA_MASK)
+                    } else if SUBNORMAL == class {
+                        runner.rng().gen_range(1, MANTISSA_MASK + 1)
+                    } else if ZERO == class {
+                        0
+                    } else if INFINITE == class {
+                        EXP_MASK
+                    } else if QUIET_NAN == class {
+                        EXP_MASK | QUIET_MASK | (random & MANTISSA_MASK)
+                    } else {
+                        EXP_MASK | runner.rng().gen_range(1, QUIET_MASK)
+                    };
+
+                    let negative = if flags.contains(Any::SIGNS) {
+                        runner.rng().gen()
+                    } else {
+                        flags.contains(NEGATIVE)
+                    };
+                    let bits = if negative { bits | SIGN_MASK } else { bits };
+
+                    Ok(BinarySearch::new_with_types(
+                        $typ::from_bits(bits), flags))
+                }
+            }
+
+            /// Strategy returned by `log_uniform()`.
+            #[derive(Clone, Copy, Debug)]
+            pub struct LogUniform {
+                lo: $typ,
+                hi: $typ,
+            }
+
+            /// Generates values from `range` spread evenly across orders of
+            /// magnitude rather than across the interval.
+            ///
+            /// Every binade (the values sharing one exponent) within the range
+            /// is equally likely, and values are uniform within it, so for
+            /// example `log_uniform(-1e300..=1e300)` produces values near 1 as
+            /// often as values near `1e300` or `1e-300`. Infinite bounds are
+            /// permitted and are then occasionally produced themselves.
+            ///
+            /// Values shrink like those from a plain range: towards 0 if the
+            /// range contains it, or else towards the bound closest to 0.
+            ///
+            /// ## Panics
+            ///
+            /// Panics if either bound is NaN or the range is empty.
+            pub fn log_uniform(range: RangeInclusive<$typ>) -> LogUniform {
+                let (lo, hi) = (*range.start(), *range.end());
+                assert!(!lo.is_nan() && !hi.is_nan() && lo <= hi,
+                        "Invalid range {}..={}", lo, hi);
+                LogUniform { lo, hi }
+            }
+
+            /// Maps a non-NaN float onto an integer such that ordering is
+            /// preserved and adjacent floats have adjacent keys. The exponent
+            /// and mantissa make up the magnitude, so a uniform key chooses
+            /// the exponent uniformly and then the mantissa uniformly.
+            fn order_key(value: $typ) -> $signed_bits {
+                let magnitude = (value.to_bits() & !SIGN_MASK) as $signed_bits;
+                if value.is_sign_negative() { -magnitude } else { magnitude }
+            }
+
+            fn from_order_key(key: $signed_bits) -> $typ {
+                let magnitude = $typ::from_bits(key.unsigned_abs());
+                if key < 0 { -magnitude } else { magnitude }
+            }
+
+            impl Strategy for LogUniform {
+                type Value = BinarySearch;
+
+                fn new_value(&self, runner: &mut TestRunner)
+                             -> Result<BinarySearch, String> {
+                    let key = rand::distributions::Range::new(
+                        order_key(self.lo), order_key(self.hi) + 1)
+                        .ind_sample(runner.rng());
+                    Ok(BinarySearch::new_clamped(
+                        self.lo, from_order_key(key), self.hi))
+                }
+            }
+
+            /// Returns the greatest value less than `end`, i.e., the greatest
+            /// value in `..end`.
+            pub(super) fn next_below(end: $typ) -> $typ {
+                if end.is_nan() || ::std::$typ::NEG_INFINITY == end {
+                    end
+                } else if 0.0 == end {
+                    -$typ::from_bits(1)
+                } else if end > 0.0 {
+                    $typ::from_bits(end.to_bits() - 1)
+                } else {
+                    $typ::from_bits(end.to_bits() + 1)
+                }
+            }
+
+            fn sample_range(runner: &mut TestRunner, lo: $typ, hi: $typ)
+                            -> $typ {
+                rand::distributions::Range::new(lo, hi)
+                    .ind_sample(runner.rng())
+            }
+
+            /// Samples a value from `lo` to `hi`, both inclusive.
+            fn sample_range_inclusive(runner: &mut TestRunner,
+                                      lo: $typ, hi: $typ) -> $typ {
+                assert!(lo <= hi, "Invalid range {}..={}", lo, hi);
+
+                let rand::Closed01(t) =
+                    runner.rng().gen::<rand::Closed01<$typ>>();
+                // Interpolate without computing `hi - lo`, which overflows
+                // for very wide ranges.
+                (lo * (1.0 - t) + hi * t).max(lo).min(hi)
+            }
+
+            numeric_api!($typ, next_below);
         }
     }
 }
 
-float_bin_search!(f32);
-float_bin_search!(f64);
+float_bin_search!(f32, u32, i32, 23, 8);
+float_bin_search!(f64, u64, i64, 52, 11);
+
+mapfn! {
+    [] fn WrapWrapping[<T : fmt::Debug>](t: T) -> Wrapping<T> {
+        Wrapping(t)
+    }
+}
+
+opaque_strategy_wrapper! {
+    /// Strategy which generates `Wrapping` values.
+    ///
+    /// Created by the `wrapping()` function in the same module.
+    #[derive(Clone, Debug)]
+    pub struct WrappingStrategy[<S>][where S : Strategy](
+        statics::Map<S, WrapWrapping>)
+        -> WrappingValueTree<S::Value>;
+    /// `ValueTree` corresponding to `WrappingStrategy`.
+    #[derive(Clone, Debug)]
+    pub struct WrappingValueTree[<T>][where T : ValueTree](
+        statics::Map<T, WrapWrapping>)
+        -> Wrapping<T::Value>;
+}
+
+/// Create a strategy which generates `Wrapping` values around those from
+/// `inner`, shrinking as `inner` does.
+pub fn wrapping<S : Strategy>(inner: S) -> WrappingStrategy<S> {
+    WrappingStrategy(statics::Map::new(inner, WrapWrapping))
+}
+
+mapfn! {
+    [] fn WrapSaturating[<T : fmt::Debug>](t: T) -> Saturating<T> {
+        Saturating(t)
+    }
+}
+
+opaque_strategy_wrapper! {
+    /// Strategy which generates `Saturating` values.
+    ///
+    /// Created by the `saturating()` function in the same module.
+    #[derive(Clone, Debug)]
+    pub struct SaturatingStrategy[<S>][where S : Strategy](
+        statics::Map<S, WrapSaturating>)
+        -> SaturatingValueTree<S::Value>;
+    /// `ValueTree` corresponding to `SaturatingStrategy`.
+    #[derive(Clone, Debug)]
+    pub struct SaturatingValueTree[<T>][where T : ValueTree](
+        statics::Map<T, WrapSaturating>)
+        -> Saturating<T::Value>;
+}
+
+/// Create a strategy which generates `Saturating` values around those from
+/// `inner`, shrinking as `inner` does.
+pub fn saturating<S : Strategy>(inner: S) -> SaturatingStrategy<S> {
+    SaturatingStrategy(statics::Map::new(inner, WrapSaturating))
+}
 
 #[cfg(test)]
 mod test {
-    use strategy::*;
-    use test_runner::*;
-
     use super::*;
 
     #[test]
@@ -548,7 +1260,8 @@ mod test {
 
         while value.simplify() { }
 
-        assert_eq!(0.0, value.current());
+        // 0.0 itself is excluded, so this is the closest value to it.
+        assert_eq!(-f64::from_bits(1), value.current());
     }
 
     #[test]
@@ -609,4 +1322,445 @@ mod test {
 
         assert_eq!(::std::f64::MIN_POSITIVE, value.current());
     }
+
+    #[test]
+    fn negative_integer_range_simplifies_to_end() {
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..100 {
+            let mut state = (-10i32..-5i32).new_value(&mut runner).unwrap();
+            while state.simplify() {
+                let v = state.current();
+                assert!((-10..-5).contains(&v), "Violated bounds: {}", v);
+            }
+
+            assert_eq!(-6, state.current());
+        }
+    }
+
+    #[test]
+    fn negative_float_range_simplifies_to_below_end() {
+        let mut runner = TestRunner::new(Config::default());
+        let below = -1.0f64 - ::std::f64::EPSILON;
+        for _ in 0..100 {
+            let mut state = (-2.0f64..-1.0).new_value(&mut runner).unwrap();
+            while state.simplify() {
+                let v = state.current();
+                assert!((-2.0..-1.0).contains(&v), "Violated bounds: {}", v);
+            }
+            assert_eq!(below, state.current());
+
+            let mut state = (..-1.0f64).new_value(&mut runner).unwrap();
+            while state.simplify() {
+                let v = state.current();
+                assert!(v < -1.0, "Violated bounds: {}", v);
+            }
+            assert_eq!(below, state.current());
+        }
+    }
+
+    #[test]
+    fn float_next_below_steps_toward_negative_infinity() {
+        assert_eq!(-1.0 - ::std::f64::EPSILON, f64::next_below(-1.0));
+        assert_eq!(1.0 - ::std::f64::EPSILON / 2.0, f64::next_below(1.0));
+        assert!(f64::next_below(0.0) < 0.0);
+        assert!(f32::next_below(0.0) < 0.0);
+    }
+
+    #[test]
+    fn inclusive_integer_range_covers_both_ends() {
+        let mut runner = TestRunner::new(Config::default());
+        let mut seen = [false; 256];
+        for _ in 0..65536 {
+            let v = (0u8..=255).new_value(&mut runner).unwrap().current();
+            seen[v as usize] = true;
+        }
+        assert!(seen[0] && seen[255]);
+
+        let mut seen = [false; 4];
+        for _ in 0..1024 {
+            let v = (-4i8..=-1).new_value(&mut runner).unwrap().current();
+            assert!((-4..=-1).contains(&v));
+            seen[(v + 4) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn inclusive_negative_range_simplifies_to_end() {
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..100 {
+            let mut state = (-42i32..=-7).new_value(&mut runner).unwrap();
+            while state.simplify() {
+                let v = state.current();
+                assert!((-42..=-7).contains(&v), "Violated bounds: {}", v);
+            }
+
+            assert_eq!(-7, state.current());
+
+            let mut state = (..=-7i64).new_value(&mut runner).unwrap();
+            while state.simplify() {
+                assert!(state.current() <= -7);
+            }
+
+            assert_eq!(-7, state.current());
+        }
+    }
+
+    #[test]
+    fn range_from_can_produce_max() {
+        let mut runner = TestRunner::new(Config::default());
+        let mut seen_max = false;
+        for _ in 0..1024 {
+            let v = (250u8..).new_value(&mut runner).unwrap().current();
+            assert!(v >= 250);
+            seen_max |= 255 == v;
+        }
+        assert!(seen_max);
+    }
+
+    #[test]
+    fn single_value_inclusive_ranges() {
+        let mut runner = TestRunner::new(Config::default());
+        assert_eq!(::std::i64::MIN, (::std::i64::MIN..=::std::i64::MIN)
+                   .new_value(&mut runner).unwrap().current());
+        assert_eq!(::std::u64::MAX, (::std::u64::MAX..=::std::u64::MAX)
+                   .new_value(&mut runner).unwrap().current());
+        assert_eq!(2.5, (2.5f32..=2.5).new_value(&mut runner)
+                   .unwrap().current());
+    }
+
+    #[test]
+    fn inclusive_float_range_stays_in_bounds() {
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let mut state = (-2.0f64..=-1.0).new_value(&mut runner).unwrap();
+            let v = state.current();
+            assert!((-2.0..=-1.0).contains(&v), "Violated bounds: {}", v);
+
+            while state.simplify() {
+                let v = state.current();
+                assert!((-2.0..=-1.0).contains(&v), "Violated bounds: {}", v);
+            }
+
+            assert_eq!(-1.0, state.current());
+        }
+    }
+
+    #[test]
+    fn full_float_range_is_finite_and_shrinks_to_zero() {
+        let mut runner = TestRunner::new(Config::default());
+        let mut saw_negative = false;
+        for _ in 0..256 {
+            let mut state = f64::FULL.new_value(&mut runner).unwrap();
+            assert!(state.current().is_finite());
+            saw_negative |= state.current() < 0.0;
+
+            while state.simplify() { }
+            assert_eq!(0.0, state.current());
+        }
+        assert!(saw_negative);
+    }
+
+    #[test]
+    fn special_values_within_range() {
+        fn seen<T : Ord, F : FnMut (&mut TestRunner) -> T>(mut sample: F)
+                                                          -> Vec<T> {
+            let mut runner = TestRunner::new(Config::default());
+            let mut values = (0..4096).map(|_| sample(&mut runner))
+                .collect::<Vec<_>>();
+            values.sort();
+            values.dedup();
+            values
+        }
+
+        assert_eq!(vec![3, 4, 5, 7, 8, 9],
+                   seen(|runner| u8::special_value(runner, 3, 10)));
+        assert_eq!(vec![-3, -2, -1, 0, 1, 2, 3],
+                   seen(|runner| i8::special_value(runner, -3, 3)));
+        let all = seen(|runner| i8::special_value(
+            runner, ::std::i8::MIN, ::std::i8::MAX));
+        assert!(all.contains(&-128) && all.contains(&-127) &&
+                all.contains(&127) && all.contains(&-65));
+        assert_eq!(vec![1 << 100],
+                   seen(|runner| u128::special_value(
+                       runner, 1 << 100, 1 << 100)));
+    }
+
+    #[test]
+    fn integers_hit_edge_cases_by_default() {
+        let mut runner = TestRunner::new(Config::default());
+        let mut seen = Vec::new();
+        for _ in 0..4096 {
+            seen.push(i32::ANY.new_value(&mut runner).unwrap().current());
+        }
+
+        for edge in &[0, 1, -1, ::std::i32::MIN, ::std::i32::MAX] {
+            assert!(seen.contains(edge), "Never saw {}", edge);
+        }
+
+        let mut seen = Vec::new();
+        for _ in 0..4096 {
+            let mut state = (100u64..1000).new_value(&mut runner).unwrap();
+            seen.push(state.current());
+            while state.simplify() {
+                assert!((100..1000).contains(&state.current()));
+            }
+        }
+        assert!(seen.contains(&100) && seen.contains(&999) &&
+                seen.contains(&512) && seen.contains(&257));
+    }
+
+    #[test]
+    fn edge_bias_can_be_disabled() {
+        let mut runner = TestRunner::new(Config {
+            edge_bias: 0.0, .. Config::default()
+        });
+        for _ in 0..4096 {
+            let v = u64::ANY.new_value(&mut runner).unwrap().current();
+            assert!(0 != v && ::std::u64::MAX != v);
+            assert!(!v.is_power_of_two());
+        }
+    }
+
+    #[test]
+    fn float_any_generates_every_class() {
+        use std::num::FpCategory;
+
+        let mut runner = TestRunner::new(Config::default());
+        let mut seen_nan = false;
+        let mut seen_infinite = false;
+        let mut seen_subnormal = false;
+        let mut seen_negative_zero = false;
+        let mut seen_negative = false;
+        for _ in 0..4096 {
+            let v = f64::ANY.new_value(&mut runner).unwrap().current();
+            match v.classify() {
+                FpCategory::Nan => seen_nan = true,
+                FpCategory::Infinite => seen_infinite = true,
+                FpCategory::Subnormal => seen_subnormal = true,
+                FpCategory::Zero =>
+                    seen_negative_zero |= v.is_sign_negative(),
+                FpCategory::Normal => seen_negative |= v < 0.0,
+            }
+        }
+
+        assert!(seen_nan && seen_infinite && seen_subnormal &&
+                seen_negative_zero && seen_negative);
+    }
+
+    #[test]
+    fn float_classes_are_respected_while_shrinking() {
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let mut state = (f32::POSITIVE | f32::NORMAL)
+                .new_value(&mut runner).unwrap();
+            assert!(state.current().is_normal() &&
+                    state.current() > 0.0);
+
+            while state.simplify() {
+                assert!(state.current().is_normal() &&
+                        state.current() > 0.0,
+                        "Bad value {}", state.current());
+            }
+            assert_eq!(::std::f32::MIN_POSITIVE, state.current());
+
+            let mut state = (f64::NEGATIVE | f64::SUBNORMAL)
+                .new_value(&mut runner).unwrap();
+            while state.simplify() {
+                assert!(0.0 > state.current() && !state.current().is_normal());
+            }
+            assert_eq!(-f64::from_bits(1), state.current());
+        }
+    }
+
+    #[test]
+    fn float_nan_shrinks_to_zero_if_permitted() {
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..16 {
+            let mut state = (f64::POSITIVE | f64::QUIET_NAN | f64::ZERO |
+                             f64::SIGNALING_NAN)
+                .new_value(&mut runner).unwrap();
+            let v = state.current();
+            assert!(v.is_nan() || 0.0 == v);
+            assert!(v.is_sign_positive());
+
+            while state.simplify() { }
+            assert_eq!(0.0, state.current());
+        }
+    }
+
+    #[test]
+    fn log_uniform_covers_all_magnitudes() {
+        let mut runner = TestRunner::new(Config::default());
+        let input = f64::log_uniform(-1e300..=1e300);
+        let mut small = 0;
+        let mut large = 0;
+        let mut negative = 0;
+        for _ in 0..1024 {
+            let mut state = input.new_value(&mut runner).unwrap();
+            let v = state.current();
+            assert!((-1e300..=1e300).contains(&v), "Out of range: {}", v);
+            if v.abs() < 1.0 { small += 1; }
+            if v.abs() > 1e150 { large += 1; }
+            if v < 0.0 { negative += 1; }
+
+            while state.simplify() { }
+            assert_eq!(0.0, state.current());
+        }
+
+        // Uniform sampling would essentially never produce a small value.
+        assert!(small > 256, "Only {} small values", small);
+        assert!(large > 128, "Only {} large values", large);
+        assert!(negative > 256, "Only {} negative values", negative);
+    }
+
+    #[test]
+    fn log_uniform_positive_range_shrinks_to_lower_bound() {
+        let mut runner = TestRunner::new(Config::default());
+        let input = f32::log_uniform(1e-10..=1e10);
+        let mut below_one = 0;
+        for _ in 0..1024 {
+            let mut state = input.new_value(&mut runner).unwrap();
+            if state.current() < 1.0 { below_one += 1; }
+
+            while state.simplify() {
+                let v = state.current();
+                assert!((1e-10..=1e10).contains(&v), "Out of range: {}", v);
+            }
+            assert_eq!(1e-10, state.current());
+        }
+
+        assert!(below_one > 384 && below_one < 640,
+                "{} values below one", below_one);
+    }
+
+    #[test]
+    fn sample_uniform_stays_within_span() {
+        let mut runner = TestRunner::new(Config::default());
+        let spans = [0, 1, 6, ::std::u64::MAX as u128 - 1,
+                     ::std::u64::MAX as u128, ::std::u64::MAX as u128 + 1,
+                     ::std::u128::MAX / 3 * 2, ::std::u128::MAX];
+        for &span in &spans {
+            for _ in 0..256 {
+                assert!(super::sample_uniform(runner.rng(), span) <= span);
+            }
+        }
+
+        let mut seen = [false; 7];
+        for _ in 0..256 {
+            seen[super::sample_uniform(runner.rng(), 6) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn i128_ranges_stay_in_bounds() {
+        let mut runner = TestRunner::new(Config::default());
+        let lo = -(1i128 << 100);
+        let hi = 1i128 << 90;
+        let mut saw_huge = false;
+        for _ in 0..256 {
+            let mut state = (lo..hi).new_value(&mut runner).unwrap();
+            saw_huge |= state.current() < -(1i128 << 64);
+            while state.simplify() {
+                assert!((lo..hi).contains(&state.current()));
+            }
+            assert_eq!(0, state.current());
+
+            let mut state = ((1u128 << 127)..).new_value(&mut runner)
+                .unwrap();
+            while state.simplify() {
+                assert!(state.current() >= 1u128 << 127);
+            }
+            assert_eq!(1u128 << 127, state.current());
+        }
+        assert!(saw_huge);
+    }
+
+    #[test]
+    fn nonzero_shrinks_to_one_with_same_sign() {
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let mut state = i16::NONZERO.new_value(&mut runner).unwrap();
+            let negative = state.current().get() < 0;
+            while state.simplify() {
+                assert_eq!(negative, state.current().get() < 0);
+            }
+            assert_eq!(if negative { -1 } else { 1 }, state.current().get());
+
+            let mut state = u128::NONZERO.new_value(&mut runner).unwrap();
+            while state.simplify() { }
+            assert_eq!(1, state.current().get());
+        }
+    }
+
+    #[test]
+    fn wrappers_shrink_like_inner() {
+        let mut runner = TestRunner::new(Config::default());
+        let mut state = wrapping(10u8..20).new_value(&mut runner).unwrap();
+        while state.simplify() { }
+        assert_eq!(::std::num::Wrapping(10), state.current());
+
+        let mut state = saturating(-20i32..-10).new_value(&mut runner)
+            .unwrap();
+        while state.simplify() { }
+        assert_eq!(::std::num::Saturating(-11), state.current());
+    }
+
+    #[test]
+    fn toward_shrinks_to_target_from_either_side() {
+        let mut runner = TestRunner::new(Config::default());
+        let input = u16::toward(1..=65535, 8080);
+        let mut seen_below = false;
+        let mut seen_above = false;
+        for _ in 0..256 {
+            let mut state = input.new_value(&mut runner).unwrap();
+            seen_below |= state.current() < 8080;
+            seen_above |= state.current() > 8080;
+            while state.simplify() {
+                assert!(state.current() >= 1);
+            }
+            assert_eq!(8080, state.current());
+        }
+        assert!(seen_below && seen_above);
+    }
+
+    #[test]
+    fn toward_converges_on_boundary() {
+        fn converge<P : Fn (i32) -> bool>(mut state: i32::TowardValueTree,
+                                          fails: P) -> i32 {
+            loop {
+                if fails(state.current()) {
+                    if !state.simplify() { break; }
+                } else if !state.complicate() {
+                    break;
+                }
+            }
+            state.current()
+        }
+
+        let mut runner = TestRunner::new(Config::default());
+        let input = i32::toward(-1000..=1000, 200);
+        for _ in 0..256 {
+            let state = input.new_value(&mut runner).unwrap();
+            let start = state.current();
+            if start >= 500 {
+                assert_eq!(500, converge(state, |v| v >= 500));
+            } else if start <= -300 {
+                assert_eq!(-300, converge(state, |v| v <= -300));
+            }
+        }
+    }
+
+    #[test]
+    fn toward_handles_extreme_targets() {
+        let mut runner = TestRunner::new(Config::default());
+        let input = i8::toward(::std::i8::MIN..=::std::i8::MAX,
+                               ::std::i8::MAX);
+        for _ in 0..256 {
+            let mut state = input.new_value(&mut runner).unwrap();
+            while state.simplify() { }
+            assert_eq!(::std::i8::MAX, state.current());
+        }
+    }
 }
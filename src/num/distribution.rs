@@ -0,0 +1,412 @@
+//-
+// Copyright 2017 Jason Lingle
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Strategies which generate numbers following common non-uniform
+//! distributions.
+//!
+//! Each strategy shrinks towards the "simplest" value of its distribution,
+//! such as the mean of a normal distribution or the most frequent rank of a
+//! Zipf distribution, and never produces or shrinks to a value outside the
+//! support of the distribution.
+//!
+//! The constructors do not validate their parameters; invalid parameters
+//! instead cause `new_value()` to return an error describing the problem.
+
+use rand::{Open01, Rng};
+use rand::distributions::{self, IndependentSample};
+
+use num;
+use strategy::*;
+use test_runner::*;
+
+/// Strategy returned by `normal()`.
+#[derive(Clone, Copy, Debug)]
+pub struct Normal {
+    mean: f64,
+    std_dev: f64,
+}
+
+/// Generates values from the normal distribution with the given mean and
+/// standard deviation.
+///
+/// Values shrink towards `mean`. Samples too large to represent are clamped
+/// to the greatest finite values.
+///
+/// `new_value()` fails unless `mean` is finite and `std_dev` is finite and
+/// not negative.
+pub fn normal(mean: f64, std_dev: f64) -> Normal {
+    Normal { mean, std_dev }
+}
+
+impl Strategy for Normal {
+    type Value = FloatOffsetValueTree;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        if !self.mean.is_finite() {
+            return Err(format!("Normal distribution mean {} is not finite",
+                               self.mean));
+        }
+        if !self.std_dev.is_finite() || self.std_dev < 0.0 {
+            return Err(format!("Invalid normal distribution standard \
+                                deviation {}", self.std_dev));
+        }
+
+        let value = distributions::Normal::new(self.mean, self.std_dev)
+            .ind_sample(runner.rng())
+            .clamp(::std::f64::MIN, ::std::f64::MAX);
+        // The offset can itself overflow when the value and the mean are
+        // extreme and of opposite signs, but adding the clamped offset back
+        // to the mean cannot.
+        let offset = (value - self.mean)
+            .clamp(::std::f64::MIN, ::std::f64::MAX);
+        Ok(FloatOffsetValueTree {
+            origin: self.mean,
+            offset: num::f64::BinarySearch::new(offset),
+        })
+    }
+}
+
+/// `ValueTree` for floats which shrink towards a value other than 0.
+#[derive(Clone, Copy, Debug)]
+pub struct FloatOffsetValueTree {
+    origin: f64,
+    offset: num::f64::BinarySearch,
+}
+
+impl ValueTree for FloatOffsetValueTree {
+    type Value = f64;
+
+    fn current(&self) -> f64 {
+        self.origin + self.offset.current()
+    }
+
+    fn simplify(&mut self) -> bool {
+        self.offset.simplify()
+    }
+
+    fn complicate(&mut self) -> bool {
+        self.offset.complicate()
+    }
+}
+
+/// Strategy returned by `exponential()`.
+#[derive(Clone, Copy, Debug)]
+pub struct Exponential {
+    lambda: f64,
+}
+
+/// Generates values from the exponential distribution with rate `lambda`,
+/// i.e., with mean `1 / lambda`.
+///
+/// Values are never negative and shrink towards 0.
+///
+/// `new_value()` fails unless `lambda` is finite and positive.
+pub fn exponential(lambda: f64) -> Exponential {
+    Exponential { lambda }
+}
+
+impl Strategy for Exponential {
+    type Value = num::f64::BinarySearch;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        if !self.lambda.is_finite() || self.lambda <= 0.0 {
+            return Err(format!("Invalid exponential distribution rate {}",
+                               self.lambda));
+        }
+
+        let value = distributions::Exp::new(self.lambda)
+            .ind_sample(runner.rng()).min(::std::f64::MAX);
+        Ok(num::f64::BinarySearch::new(value))
+    }
+}
+
+/// Strategy returned by `log_uniform()`.
+#[derive(Clone, Copy, Debug)]
+pub struct LogUniform {
+    lo: f64,
+    hi: f64,
+}
+
+/// Generates values between `lo` and `hi` inclusive whose logarithm is
+/// uniformly distributed, so that, for example, values between 1 and 10 are
+/// as likely as values between 10 and 100.
+///
+/// Unlike `num::f64::log_uniform()`, which picks uniformly among the binary
+/// exponents of a range which may include zero and negative numbers, this is
+/// the continuous distribution over a positive range.
+///
+/// Values shrink towards `lo`.
+///
+/// `new_value()` fails unless `0 < lo <= hi` and both are finite.
+pub fn log_uniform(lo: f64, hi: f64) -> LogUniform {
+    LogUniform { lo, hi }
+}
+
+impl Strategy for LogUniform {
+    type Value = num::f64::BinarySearch;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        if !(self.lo > 0.0 && self.lo <= self.hi && self.hi.is_finite()) {
+            return Err(format!("Invalid log-uniform distribution bounds \
+                                {}..={}", self.lo, self.hi));
+        }
+
+        let (ln_lo, ln_hi) = (self.lo.ln(), self.hi.ln());
+        let t = runner.rng().next_f64();
+        let value = (ln_lo + (ln_hi - ln_lo) * t).exp()
+            .max(self.lo).min(self.hi);
+        Ok(num::f64::BinarySearch::new_clamped(self.lo, value, self.hi))
+    }
+}
+
+/// Strategy returned by `geometric()`.
+#[derive(Clone, Copy, Debug)]
+pub struct Geometric {
+    p: f64,
+}
+
+/// Generates the number of failed trials before the first success, where
+/// each trial succeeds with probability `p`.
+///
+/// Values shrink towards 0.
+///
+/// `new_value()` fails unless `0 < p <= 1`.
+pub fn geometric(p: f64) -> Geometric {
+    Geometric { p }
+}
+
+impl Strategy for Geometric {
+    type Value = num::u64::BinarySearch;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        if !(self.p > 0.0 && self.p <= 1.0) {
+            return Err(format!("Invalid geometric distribution probability \
+                                {}", self.p));
+        }
+
+        let value = if 1.0 == self.p {
+            0
+        } else {
+            // Inverse transform sampling. The float to integer conversion
+            // saturates for extremely small `p`.
+            let Open01(u) = runner.rng().gen::<Open01<f64>>();
+            (u.ln() / (-self.p).ln_1p()).floor() as u64
+        };
+        Ok(num::u64::BinarySearch::new(value))
+    }
+}
+
+/// Strategy returned by `zipf()`.
+#[derive(Clone, Copy, Debug)]
+pub struct Zipf {
+    n: u64,
+    s: f64,
+}
+
+/// Generates ranks from 1 to `n` inclusive following Zipf's law with
+/// exponent `s`; i.e., the probability of rank `k` is proportional to
+/// `1 / k^s`.
+///
+/// This is useful for modelling things like cache keys or words, where a
+/// few values are very common and most are rare. Values shrink towards 1,
+/// the most common rank.
+///
+/// `new_value()` fails unless `n` is at least 1 and `s` is finite and not
+/// negative.
+pub fn zipf(n: u64, s: f64) -> Zipf {
+    Zipf { n, s }
+}
+
+impl Zipf {
+    /// Inverse of the integral of `x^-s` from 1, scaled so that `p = 1` maps
+    /// to `n`.
+    fn inverse_cdf(&self, p: f64, t: f64) -> f64 {
+        let pt = p * t;
+        if pt <= 1.0 {
+            pt
+        } else if 1.0 == self.s {
+            (pt - 1.0).exp()
+        } else {
+            (pt * (1.0 - self.s) + self.s).powf(1.0 / (1.0 - self.s))
+        }
+    }
+}
+
+impl Strategy for Zipf {
+    type Value = num::u64::BinarySearch;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        if 0 == self.n {
+            return Err("Zipf distribution needs at least one rank"
+                       .to_owned());
+        }
+        if !self.s.is_finite() || self.s < 0.0 {
+            return Err(format!("Invalid Zipf distribution exponent {}",
+                               self.s));
+        }
+
+        // Rejection-inversion sampling, as described by Hörmann and
+        // Derflinger in "Rejection-inversion to generate variates from
+        // monotone discrete distributions".
+        let n = self.n as f64;
+        let t = if 1.0 == self.s {
+            1.0 + n.ln()
+        } else {
+            (n.powf(1.0 - self.s) - self.s) / (1.0 - self.s)
+        };
+
+        let value = loop {
+            let inv_b = self.inverse_cdf(runner.rng().next_f64(), t);
+            let x = (inv_b + 1.0).floor();
+            let mut ratio = x.powf(-self.s);
+            if x > 1.0 {
+                ratio *= inv_b.powf(self.s);
+            }
+
+            if runner.rng().next_f64() < ratio {
+                break (x as u64).max(1).min(self.n);
+            }
+        };
+        Ok(num::u64::BinarySearch::new_above(1, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mean_of<S : Strategy>(strategy: S, samples: usize) -> f64
+    where <S::Value as ValueTree>::Value : Into<f64> {
+        let mut runner = TestRunner::new(Config::default());
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            sum += strategy.new_value(&mut runner).unwrap().current().into();
+        }
+        sum / samples as f64
+    }
+
+    #[test]
+    fn invalid_parameters_are_errors() {
+        let mut runner = TestRunner::new(Config::default());
+        assert!(normal(0.0, -1.0).new_value(&mut runner).is_err());
+        assert!(normal(::std::f64::NAN, 1.0).new_value(&mut runner).is_err());
+        assert!(exponential(0.0).new_value(&mut runner).is_err());
+        assert!(log_uniform(0.0, 10.0).new_value(&mut runner).is_err());
+        assert!(log_uniform(10.0, 1.0).new_value(&mut runner).is_err());
+        assert!(geometric(0.0).new_value(&mut runner).is_err());
+        assert!(geometric(1.5).new_value(&mut runner).is_err());
+        assert!(zipf(0, 1.0).new_value(&mut runner).is_err());
+        assert!(zipf(10, -1.0).new_value(&mut runner).is_err());
+    }
+
+    #[test]
+    fn normal_is_centred_and_shrinks_to_mean() {
+        let mean = mean_of(normal(100.0, 5.0), 4096);
+        assert!((mean - 100.0).abs() < 1.0, "Mean was {}", mean);
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let mut state = normal(100.0, 5.0).new_value(&mut runner)
+                .unwrap();
+            while state.simplify() { }
+            assert_eq!(100.0, state.current());
+        }
+    }
+
+    #[test]
+    fn normal_with_extreme_parameters_is_finite() {
+        let mut runner = TestRunner::new(Config::default());
+        for &(mean, std_dev) in &[(-1e308, 1e308), (1e308, 1e308),
+                                  (0.0, ::std::f64::MAX)] {
+            for _ in 0..256 {
+                let mut state = normal(mean, std_dev).new_value(&mut runner)
+                    .unwrap();
+                assert!(state.current().is_finite(),
+                        "Got {} from normal({:e}, {:e})",
+                        state.current(), mean, std_dev);
+                while state.simplify() {
+                    assert!(state.current().is_finite());
+                }
+                assert_eq!(mean, state.current());
+            }
+        }
+    }
+
+    #[test]
+    fn exponential_has_expected_mean() {
+        let mean = mean_of(exponential(0.5), 4096);
+        assert!((mean - 2.0).abs() < 0.25, "Mean was {}", mean);
+    }
+
+    #[test]
+    fn log_uniform_stays_in_bounds() {
+        let mut runner = TestRunner::new(Config::default());
+        let mut below_ten = 0;
+        for _ in 0..1024 {
+            let mut state = log_uniform(1.0, 1000.0).new_value(&mut runner)
+                .unwrap();
+            if state.current() < 10.0 { below_ten += 1; }
+            while state.simplify() {
+                assert!((1.0..=1000.0).contains(&state.current()));
+            }
+            assert_eq!(1.0, state.current());
+        }
+        // A third of the values should be below 10.
+        assert!(below_ten > 256 && below_ten < 448,
+                "{} values below 10", below_ten);
+    }
+
+    #[test]
+    fn geometric_has_expected_mean() {
+        let mut runner = TestRunner::new(Config::default());
+        let mut sum = 0u64;
+        for _ in 0..4096 {
+            sum += geometric(0.25).new_value(&mut runner).unwrap().current();
+        }
+        // The mean number of failures is (1 - p) / p = 3.
+        let mean = sum as f64 / 4096.0;
+        assert!((mean - 3.0).abs() < 0.4, "Mean was {}", mean);
+        assert_eq!(0, geometric(1.0).new_value(&mut runner).unwrap()
+                   .current());
+    }
+
+    #[test]
+    fn zipf_favours_low_ranks_and_shrinks_to_one() {
+        let mut runner = TestRunner::new(Config::default());
+        let mut counts = [0u32; 11];
+        for _ in 0..4096 {
+            let mut state = zipf(10, 1.0).new_value(&mut runner).unwrap();
+            let v = state.current();
+            assert!((1..=10).contains(&v), "Rank {} out of range", v);
+            counts[v as usize] += 1;
+
+            while state.simplify() {
+                assert!(state.current() >= 1);
+            }
+            assert_eq!(1, state.current());
+        }
+
+        // Rank 1 has probability 1/H(10) ≈ 0.34 and rank 10 one tenth of
+        // that.
+        assert!(counts[1] > 1200 && counts[1] < 1600,
+                "Rank 1 seen {} times", counts[1]);
+        assert!(counts[1] > counts[2] && counts[2] > counts[10]);
+
+        for _ in 0..256 {
+            let v = zipf(1_000_000_000, 1.5).new_value(&mut runner).unwrap()
+                .current();
+            assert!((1..=1_000_000_000).contains(&v));
+        }
+    }
+}
@@ -11,6 +11,7 @@
 
 use std::fmt;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use strategy::*;
 use test_runner::*;
@@ -53,15 +54,29 @@ opaque_strategy_wrapper! {
     /// Constructed by other functions in this module.
     #[derive(Clone)]
     pub struct OptionStrategy[<T>][where T : Strategy]
-        (TupleUnion<((u32,NoneStrategy<<T::Value as ValueTree>::Value>),
-                     (u32,statics::Map<T, WrapSome>))>)
-        -> OptionValueTree<T::Value>;
+        (TupleUnion<((u32,Arc<NoneStrategy<<T::Value as ValueTree>::Value>>),
+                     (u32,Arc<statics::Map<T, WrapSome>>))>)
+        -> OptionValueTree<T>;
     /// `ValueTree` type corresponding to `OptionStrategy`.
-    #[derive(Clone, Debug)]
-    pub struct OptionValueTree[<T>][where T : ValueTree]
-        (TupleUnionValueTree<(NoneStrategy<T::Value>,
-                              Option<statics::Map<T, WrapSome>>)>)
-        -> Option<T::Value>;
+    pub struct OptionValueTree[<T>][where T : Strategy]
+        (TupleUnionValueTree<(
+            LazyValueTree<NoneStrategy<<T::Value as ValueTree>::Value>>,
+            LazyValueTree<statics::Map<T, WrapSome>>)>)
+        -> Option<<T::Value as ValueTree>::Value>;
+}
+
+// `#[derive]` can't express the `T::Value` bounds these need.
+impl<T : Strategy> Clone for OptionValueTree<T> where T::Value : Clone {
+    fn clone(&self) -> Self {
+        OptionValueTree(self.0.clone())
+    }
+}
+
+impl<T : Strategy> fmt::Debug for OptionValueTree<T>
+where T::Value : fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OptionValueTree({:?})", self.0)
+    }
 }
 
 // XXX Unclear why this is necessary; #[derive(Debug)] *should* generate
@@ -95,8 +110,8 @@ pub fn weighted<T : Strategy>(probability_of_some: f64, t: T)
     let (weight_some, weight_none) = float_to_weight(probability_of_some);
 
     OptionStrategy(TupleUnion::new((
-        (weight_none, NoneStrategy(PhantomData)),
-        (weight_some, statics::Map::new(t, WrapSome)),
+        (weight_none, Arc::new(NoneStrategy(PhantomData))),
+        (weight_some, Arc::new(statics::Map::new(t, WrapSome))),
     )))
 }
 
@@ -0,0 +1,302 @@
+//-
+// Copyright 2017 Jason Lingle
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Strategies for generating `OsString`s and `PathBuf`s.
+//!
+//! Characters are biased toward `char::DEFAULT_SPECIAL_CHARS`, which includes
+//! many characters that give filesystem logic difficulties. On Unix, values
+//! may also contain bytes which are not valid UTF-8, and on Windows unpaired
+//! surrogates.
+
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::path::{self, PathBuf};
+
+use bytes;
+use char::{self, CharStrategy};
+use collection;
+use strategy::*;
+use string;
+use test_runner::*;
+
+opaque_strategy_wrapper! {
+    /// Strategy which generates `OsString`s.
+    ///
+    /// Created by the `os_string()` and `file_name()` functions in the same
+    /// module.
+    #[derive(Debug)]
+    pub struct OsStringStrategy[][](BoxedStrategy<OsString>)
+        -> OsStringValueTree;
+    /// `ValueTree` corresponding to `OsStringStrategy`.
+    pub struct OsStringValueTree[][](Box<ValueTree<Value = OsString>>)
+        -> OsString;
+}
+
+opaque_strategy_wrapper! {
+    /// Strategy which generates `PathBuf`s.
+    ///
+    /// Created by the `path_buf()` function in the same module.
+    #[derive(Debug)]
+    pub struct PathBufStrategy[][](BoxedStrategy<PathBuf>)
+        -> PathBufValueTree;
+    /// `ValueTree` corresponding to `PathBufStrategy`.
+    pub struct PathBufValueTree[][](Box<ValueTree<Value = PathBuf>>)
+        -> PathBuf;
+}
+
+/// Creates a strategy which generates arbitrary `OsString`s, which may
+/// contain any character, including path separators and NUL, as well as
+/// anything else the platform permits.
+///
+/// Values which are valid Unicode have up to 32 characters. Those which are
+/// not, as generated by `bytes::malformed_utf8()` on Unix or `bytes::wtf8()`
+/// elsewhere, can be somewhat longer. Values shrink toward shorter valid
+/// Unicode strings.
+pub fn os_string() -> OsStringStrategy {
+    OsStringStrategy(Union::new_weighted(vec![
+        (4, string::string_of(char::ANY, 0..32)
+         .prop_map(OsString::from).boxed()),
+        (1, non_unicode().prop_map(from_platform_bytes).boxed()),
+    ]).boxed())
+}
+
+/// Creates a strategy which generates `OsString`s suitable as a single path
+/// component, i.e., non-empty and without path separators or NUL.
+///
+/// Besides short names, there is a chance of generating names of several
+/// hundred characters, beyond the limit of most filesystems, and names which
+/// are not valid Unicode. Values shrink toward shorter names.
+pub fn file_name() -> OsStringStrategy {
+    OsStringStrategy(Union::new_weighted(vec![
+        (8, string::string_of(name_char(), 1..16)
+         .prop_map(OsString::from).boxed()),
+        (1, string::string_of(char::range('a', 'z'), 200..300)
+         .prop_map(OsString::from).boxed()),
+        (1, non_unicode().prop_map(|mut bytes| {
+            for b in &mut bytes {
+                if 0 == *b || path::is_separator(*b as char) {
+                    *b = b'_';
+                }
+            }
+            from_platform_bytes(bytes)
+        }).boxed()),
+    ]).boxed())
+}
+
+/// Creates a strategy which generates `PathBuf`s of up to 8 components, using
+/// the platform's separator.
+///
+/// Paths may be relative or absolute and include `.`, `..` and empty
+/// components (i.e., repeated separators) as well as any name generated by
+/// `file_name()`. Values shrink toward relative paths with fewer components
+/// and shorter names.
+pub fn path_buf() -> PathBufStrategy {
+    let component = Union::new_weighted(vec![
+        (8, file_name().boxed()),
+        (1, Just(OsString::from(".")).boxed()),
+        (1, Just(OsString::from("..")).boxed()),
+        (1, Just(OsString::new()).boxed()),
+    ]);
+    let root = Union::new(ROOTS.iter().map(|&root| Just(root)));
+
+    PathBufStrategy(
+        (root, collection::vec(component, 0..8))
+            .prop_map(|(root, components)| {
+                let mut path = OsString::from(root);
+                for (ix, component) in components.into_iter().enumerate() {
+                    if ix > 0 {
+                        path.push(path::MAIN_SEPARATOR.to_string());
+                    }
+                    path.push(component);
+                }
+                PathBuf::from(path)
+            }).boxed())
+}
+
+/// The prefixes used for paths. The first is relative.
+#[cfg(not(windows))]
+const ROOTS: &[&str] = &["", "/", "//"];
+#[cfg(windows)]
+const ROOTS: &[&str] = &["", "\\", "C:\\", "C:", "\\\\server\\share\\",
+                         "\\\\?\\C:\\"];
+
+fn name_char() -> CharStrategy<'static> {
+    let excluded = |c: char| '\0' == c || path::is_separator(c);
+
+    let mut ranges = vec![];
+    let mut start = '\x01';
+    for c in (0u8..0x80).map(|b| b as char).filter(|&c| excluded(c)) {
+        if c > start {
+            ranges.push((start, (c as u8 - 1) as char));
+        }
+        start = (c as u8 + 1) as char;
+    }
+    ranges.push((start, ::std::char::MAX));
+
+    CharStrategy::new(
+        Cow::Owned(char::DEFAULT_SPECIAL_CHARS.iter().cloned()
+                   .filter(|&c| !excluded(c)).collect()),
+        Cow::Borrowed(char::DEFAULT_PREFERRED_RANGES),
+        Cow::Owned(ranges))
+}
+
+/// Returns a strategy for byte strings which are not valid UTF-8 but are
+/// valid as `OsString`s on this platform once passed to
+/// `from_platform_bytes()`.
+#[cfg(unix)]
+fn non_unicode() -> BoxedStrategy<Vec<u8>> {
+    bytes::malformed_utf8().boxed()
+}
+
+#[cfg(not(unix))]
+fn non_unicode() -> BoxedStrategy<Vec<u8>> {
+    bytes::wtf8().prop_filter("no unpaired surrogates".to_owned(),
+                              |bytes| ::std::str::from_utf8(bytes).is_err())
+        .boxed()
+}
+
+#[cfg(unix)]
+fn from_platform_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+/// Converts WTF-8 as generated by `bytes::wtf8()` to an `OsString`.
+#[cfg(windows)]
+fn from_platform_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut wide = vec![];
+    let mut ix = 0;
+    while ix < bytes.len() {
+        let (len, lead_bits) = match bytes[ix] {
+            0x00..=0x7F => (1, 0x7F),
+            0xC0..=0xDF => (2, 0x1F),
+            0xE0..=0xEF => (3, 0x0F),
+            _ => (4, 0x07),
+        };
+        let cp = bytes[ix + 1..ix + len].iter().fold(
+            (bytes[ix] & lead_bits) as u32,
+            |cp, &b| cp << 6 | (b & 0x3F) as u32);
+        if cp >= 0x10000 {
+            wide.push((0xD800 + ((cp - 0x10000) >> 10)) as u16);
+            wide.push((0xDC00 + ((cp - 0x10000) & 0x3FF)) as u16);
+        } else {
+            wide.push(cp as u16);
+        }
+        ix += len;
+    }
+    OsString::from_wide(&wide)
+}
+
+/// There is no way to build an `OsString` which is not valid Unicode here, so
+/// the bytes are decoded lossily.
+#[cfg(not(any(unix, windows)))]
+fn from_platform_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Component;
+
+    use super::*;
+
+    #[test]
+    fn file_names_are_single_components() {
+        let strategy = file_name();
+        let mut runner = TestRunner::new(Config::default());
+        let mut long = 0;
+        let mut non_unicode = 0;
+        for _ in 0..256 {
+            let mut value = strategy.new_value(&mut runner).unwrap();
+            if value.current().len() > 255 {
+                long += 1;
+            }
+            if value.current().to_str().is_none() {
+                non_unicode += 1;
+            }
+
+            loop {
+                let name = value.current();
+                let components = path::Path::new(&name).components()
+                    .collect::<Vec<_>>();
+                assert!(!name.is_empty());
+                match components[..] {
+                    [Component::Normal(component)] |
+                    [Component::CurDir, Component::Normal(component)] =>
+                        assert_eq!(name, component),
+                    // `.` and `..` may be generated as ordinary names
+                    [Component::CurDir] | [Component::ParentDir] => (),
+                    _ => panic!("Not a single component: {:?}", name),
+                }
+                if !value.simplify() { break; }
+            }
+        }
+        assert!(long > 0);
+        if cfg!(any(unix, windows)) {
+            assert!(non_unicode > 0);
+        }
+    }
+
+    #[test]
+    fn paths_have_varied_structure() {
+        let strategy = path_buf();
+        let mut runner = TestRunner::new(Config::default());
+        let (mut absolute, mut relative, mut dots, mut empty) = (0, 0, 0, 0);
+        for _ in 0..256 {
+            let path = strategy.new_value(&mut runner).unwrap().current();
+            let os = path.as_os_str().to_string_lossy().into_owned();
+            if path.has_root() {
+                absolute += 1;
+            } else {
+                relative += 1;
+            }
+            if path.components().any(|c| Component::ParentDir == c) {
+                dots += 1;
+            }
+            let separator = path::MAIN_SEPARATOR.to_string();
+            if os.contains(&(separator.clone() + &separator)) {
+                empty += 1;
+            }
+        }
+        assert!(absolute > 32 && relative > 32 && dots > 32 && empty > 32,
+                "absolute = {}, relative = {}, dots = {}, empty = {}",
+                absolute, relative, dots, empty);
+    }
+
+    #[test]
+    fn paths_shrink_to_few_short_components() {
+        let strategy = path_buf();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let case = strategy.new_value(&mut runner).unwrap();
+            // Pretend that any path with more than one component fails.
+            let result = runner.run_one(case, |path| {
+                if path.components().count() > 1 {
+                    Err(TestCaseError::Fail("too many".to_owned()))
+                } else {
+                    Ok(())
+                }
+            });
+
+            match result {
+                Ok(_) => (),
+                Err(TestError::Fail(_, path)) => {
+                    assert_eq!(2, path.components().count(),
+                               "Not minimal: {:?}", path);
+                    // Such as `a/b`, `/a` or `a/..`
+                    assert!(path.to_string_lossy().chars().count() <= 4,
+                            "Not minimal: {:?}", path);
+                },
+                e => panic!("Unexpected result: {:?}", e),
+            }
+        }
+    }
+}
@@ -12,8 +12,13 @@
 //! This module is intended to be wildcard-imported, i.e.,
 //! `use proptest::prelude::*;`. Note that it re-exports the whole crate itself
 //! under the name `prop`, so you don't need a separate `use proptest;` line.
+//!
+//! The `Rng` trait from `rand` is also re-exported so that functions passed
+//! to `Strategy::prop_perturb()` can use it without depending on `rand`
+//! directly.
 
 pub use strategy::{BoxedStrategy, Just, Strategy};
+pub use rand::Rng;
 pub use test_runner::Config as ProptestConfig;
 pub use test_runner::TestCaseError;
 
@@ -26,11 +31,15 @@ pub mod prop {
     pub use bool;
     pub use num;
     pub use bits;
+    pub use bytes;
     pub use tuple;
     pub use array;
     pub use collection;
     pub use char;
     pub use string;
+    pub use path;
+    pub use text;
+    pub use grammar;
     pub use option;
     pub use result;
 }
@@ -28,6 +28,7 @@
 
 use std::fmt;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use strategy::*;
 use test_runner::*;
@@ -72,17 +73,19 @@ opaque_strategy_wrapper! {
     /// Shrinks to `Err`.
     #[derive(Clone)]
     pub struct MaybeOk[<T, E>][where T : Strategy, E : Strategy]
-        (TupleUnion<((u32, statics::Map<E, WrapErr<<T::Value as ValueTree>::Value,
-                                                   <E::Value as ValueTree>::Value>>),
-                     (u32, statics::Map<T, WrapOk<<T::Value as ValueTree>::Value,
-                                                  <E::Value as ValueTree>::Value>>))>)
-        -> MaybeOkValueTree<T::Value, E::Value>;
+        (TupleUnion<((u32, Arc<statics::Map<E, WrapErr<<T::Value as ValueTree>::Value,
+                                                       <E::Value as ValueTree>::Value>>>),
+                     (u32, Arc<statics::Map<T, WrapOk<<T::Value as ValueTree>::Value,
+                                                      <E::Value as ValueTree>::Value>>>))>)
+        -> MaybeOkValueTree<T, E>;
     /// `ValueTree` type corresponding to `MaybeOk`.
-    #[derive(Clone, Debug)]
-    pub struct MaybeOkValueTree[<T, E>][where T : ValueTree, E : ValueTree]
-        (TupleUnionValueTree<(statics::Map<E, WrapErr<T::Value, E::Value>>,
-                              Option<statics::Map<T, WrapOk<T::Value, E::Value>>>)>)
-        -> Result<T::Value, E::Value>;
+    pub struct MaybeOkValueTree[<T, E>][where T : Strategy, E : Strategy]
+        (TupleUnionValueTree<(
+            LazyValueTree<statics::Map<E, WrapErr<<T::Value as ValueTree>::Value,
+                                                  <E::Value as ValueTree>::Value>>>,
+            LazyValueTree<statics::Map<T, WrapOk<<T::Value as ValueTree>::Value,
+                                                 <E::Value as ValueTree>::Value>>>)>)
+        -> Result<<T::Value as ValueTree>::Value, <E::Value as ValueTree>::Value>;
 }
 
 opaque_strategy_wrapper! {
@@ -92,17 +95,45 @@ opaque_strategy_wrapper! {
     /// Shrinks to `Ok`.
     #[derive(Clone)]
     pub struct MaybeErr[<T, E>][where T : Strategy, E : Strategy]
-        (TupleUnion<((u32, statics::Map<T, WrapOk<<T::Value as ValueTree>::Value,
-                                                  <E::Value as ValueTree>::Value>>),
-                     (u32, statics::Map<E, WrapErr<<T::Value as ValueTree>::Value,
-                                                   <E::Value as ValueTree>::Value>>))>)
-        -> MaybeErrValueTree<T::Value, E::Value>;
+        (TupleUnion<((u32, Arc<statics::Map<T, WrapOk<<T::Value as ValueTree>::Value,
+                                                      <E::Value as ValueTree>::Value>>>),
+                     (u32, Arc<statics::Map<E, WrapErr<<T::Value as ValueTree>::Value,
+                                                       <E::Value as ValueTree>::Value>>>))>)
+        -> MaybeErrValueTree<T, E>;
     /// `ValueTree` type corresponding to `MaybeErr`.
-    #[derive(Clone, Debug)]
-    pub struct MaybeErrValueTree[<T, E>][where T : ValueTree, E : ValueTree]
-        (TupleUnionValueTree<(statics::Map<T, WrapOk<T::Value, E::Value>>,
-                              Option<statics::Map<E, WrapErr<T::Value, E::Value>>>)>)
-        -> Result<T::Value, E::Value>;
+    pub struct MaybeErrValueTree[<T, E>][where T : Strategy, E : Strategy]
+        (TupleUnionValueTree<(
+            LazyValueTree<statics::Map<T, WrapOk<<T::Value as ValueTree>::Value,
+                                                 <E::Value as ValueTree>::Value>>>,
+            LazyValueTree<statics::Map<E, WrapErr<<T::Value as ValueTree>::Value,
+                                                  <E::Value as ValueTree>::Value>>>)>)
+        -> Result<<T::Value as ValueTree>::Value, <E::Value as ValueTree>::Value>;
+}
+
+// `#[derive]` can't express the `T::Value` and `E::Value` bounds these need.
+impl<T : Strategy, E : Strategy> Clone for MaybeOkValueTree<T, E>
+where T::Value : Clone, E::Value : Clone {
+    fn clone(&self) -> Self {
+        MaybeOkValueTree(self.0.clone())
+    }
+}
+impl<T : Strategy, E : Strategy> fmt::Debug for MaybeOkValueTree<T, E>
+where T::Value : fmt::Debug, E::Value : fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MaybeOkValueTree({:?})", self.0)
+    }
+}
+impl<T : Strategy, E : Strategy> Clone for MaybeErrValueTree<T, E>
+where T::Value : Clone, E::Value : Clone {
+    fn clone(&self) -> Self {
+        MaybeErrValueTree(self.0.clone())
+    }
+}
+impl<T : Strategy, E : Strategy> fmt::Debug for MaybeErrValueTree<T, E>
+where T::Value : fmt::Debug, E::Value : fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MaybeErrValueTree({:?})", self.0)
+    }
 }
 
 // These need to exist for the same reason as the one on `OptionStrategy`
@@ -142,8 +173,10 @@ pub fn maybe_ok_weighted<T : Strategy, E : Strategy>(
     let (ok_weight, err_weight) = float_to_weight(probability_of_ok);
 
     MaybeOk(TupleUnion::new((
-        (err_weight, statics::Map::new(e, WrapErr(PhantomData, PhantomData))),
-        (ok_weight, statics::Map::new(t, WrapOk(PhantomData, PhantomData))),
+        (err_weight, Arc::new(statics::Map::new(
+            e, WrapErr(PhantomData, PhantomData)))),
+        (ok_weight, Arc::new(statics::Map::new(
+            t, WrapOk(PhantomData, PhantomData)))),
     )))
 }
 
@@ -170,8 +203,10 @@ pub fn maybe_err_weighted<T : Strategy, E : Strategy>(
     let (err_weight, ok_weight) = float_to_weight(probability_of_err);
 
     MaybeErr(TupleUnion::new((
-        (ok_weight, statics::Map::new(t, WrapOk(PhantomData, PhantomData))),
-        (err_weight, statics::Map::new(e, WrapErr(PhantomData, PhantomData))),
+        (ok_weight, Arc::new(statics::Map::new(
+            t, WrapOk(PhantomData, PhantomData)))),
+        (err_weight, Arc::new(statics::Map::new(
+            e, WrapErr(PhantomData, PhantomData)))),
     )))
 }
 
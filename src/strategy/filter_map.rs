@@ -0,0 +1,185 @@
+//-
+// Copyright 2017 Jason Lingle
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Arc;
+
+use strategy::traits::*;
+use test_runner::*;
+
+/// `Strategy` combined filter and map adaptor.
+///
+/// See `Strategy::prop_filter_map()`.
+pub struct FilterMap<S, F> {
+    pub(super) source: S,
+    pub(super) whence: String,
+    pub(super) fun: Arc<F>,
+}
+
+impl<S : fmt::Debug, F> fmt::Debug for FilterMap<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FilterMap")
+            .field("source", &self.source)
+            .field("whence", &self.whence)
+            .field("fun", &"<function>")
+            .finish()
+    }
+}
+
+impl<S : Clone, F> Clone for FilterMap<S, F> {
+    fn clone(&self) -> Self {
+        FilterMap {
+            source: self.source.clone(),
+            whence: self.whence.clone(),
+            fun: self.fun.clone(),
+        }
+    }
+}
+
+impl<S : Strategy, O : fmt::Debug,
+     F : Fn (<S::Value as ValueTree>::Value) -> Option<O>>
+Strategy for FilterMap<S, F> {
+    type Value = FilterMapValueTree<S::Value, F, O>;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        loop {
+            let val = self.source.new_value(runner)?;
+            if let Some(current) = (self.fun)(val.current()) {
+                return Ok(FilterMapValueTree {
+                    source: val,
+                    fun: self.fun.clone(),
+                    current: Cell::new(Some(current)),
+                })
+            } else {
+                runner.reject_local(self.whence.clone())?;
+            }
+        }
+    }
+}
+
+/// `ValueTree` corresponding to `FilterMap`.
+pub struct FilterMapValueTree<V, F, O> {
+    source: V,
+    fun: Arc<F>,
+    // The output for the current source value, if it has been computed but
+    // not yet returned from `current()`. This lets the common case of
+    // checking a new source value and then immediately reading it call `fun`
+    // only once.
+    current: Cell<Option<O>>,
+}
+
+impl<V : fmt::Debug, F, O> fmt::Debug for FilterMapValueTree<V, F, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FilterMapValueTree")
+            .field("source", &self.source)
+            .field("fun", &"<function>")
+            .finish()
+    }
+}
+
+impl<V : ValueTree, F : Fn (V::Value) -> Option<O>, O : fmt::Debug>
+FilterMapValueTree<V, F, O> {
+    fn ensure_acceptable(&mut self) {
+        loop {
+            if let Some(current) = (self.fun)(self.source.current()) {
+                self.current.set(Some(current));
+                break;
+            } else if !self.source.complicate() {
+                panic!("Unable to complicate filtered strategy \
+                        back into acceptable value");
+            }
+        }
+    }
+}
+
+impl<V : ValueTree, F : Fn (V::Value) -> Option<O>, O : fmt::Debug>
+ValueTree for FilterMapValueTree<V, F, O> {
+    type Value = O;
+
+    fn current(&self) -> O {
+        if let Some(current) = self.current.take() {
+            current
+        } else {
+            (self.fun)(self.source.current()).expect(
+                "Filter-map function rejected a value it previously \
+                 accepted")
+        }
+    }
+
+    fn simplify(&mut self) -> bool {
+        if self.source.simplify() {
+            self.ensure_acceptable();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn complicate(&mut self) -> bool {
+        if self.source.complicate() {
+            self.ensure_acceptable();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_map() {
+        let input = (0..256).prop_filter_map(
+            "%3".to_owned(), |v| if 0 == v % 3 { Some(v / 3) } else { None });
+
+        for _ in 0..256 {
+            let mut runner = TestRunner::new(Config::default());
+            let mut case = input.new_value(&mut runner).unwrap();
+
+            assert!(case.current() < 86);
+
+            while case.simplify() {
+                assert!(case.current() < 86);
+            }
+            assert!(case.current() < 86);
+        }
+    }
+
+    #[test]
+    fn filter_map_calls_function_once_per_new_value() {
+        let calls = Cell::new(0);
+        let input = (0..256).prop_filter_map("never".to_owned(), |v| {
+            calls.set(calls.get() + 1);
+            Some(v)
+        });
+
+        let mut runner = TestRunner::new(Config::default());
+        let case = input.new_value(&mut runner).unwrap();
+        case.current();
+        assert_eq!(1, calls.get());
+    }
+
+    #[test]
+    fn filter_map_records_local_rejects() {
+        let input = (0..256).prop_filter_map(
+            "odd".to_owned(), |v| if 0 == v % 2 { Some(v) } else { None });
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            assert!(0 == input.new_value(&mut runner).unwrap().current() % 2);
+        }
+        assert!(runner.to_string().contains("times at odd"));
+    }
+}
@@ -0,0 +1,361 @@
+//-
+// Copyright 2017 Jason Lingle
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use strategy::traits::*;
+use test_runner::*;
+
+/// A `Strategy` which always produces a single value, computed by calling a
+/// function each time the value is needed.
+///
+/// Unlike `Just`, this does not require the produced type to be `Clone`, nor
+/// does it construct the value until it is actually requested. The function
+/// is called once per call to `ValueTree::current()`, so it should be cheap
+/// enough to call repeatedly and should always produce equivalent values.
+///
+/// ## Example
+///
+/// ```
+/// use std::rc::Rc;
+///
+/// use proptest::prelude::*;
+/// use proptest::strategy::{LazyJust, ValueTree};
+/// use proptest::test_runner::{Config, TestRunner};
+///
+/// # fn main() {
+/// // `Rc` cannot be sent between threads, and each generated value should
+/// // own a distinct allocation.
+/// let strategy = LazyJust::new(|| Rc::new(vec![1, 2, 3]));
+///
+/// let mut runner = TestRunner::new(Config::default());
+/// let value = strategy.new_value(&mut runner).unwrap().current();
+/// assert_eq!(vec![1, 2, 3], *value);
+/// # }
+/// ```
+pub struct LazyJust<T, F : Fn () -> T> {
+    function: Arc<F>,
+}
+
+impl<T, F : Fn () -> T> LazyJust<T, F> {
+    /// Create a strategy which produces values by calling `function`.
+    pub fn new(function: F) -> Self {
+        LazyJust { function: Arc::new(function) }
+    }
+}
+
+impl<T, F : Fn () -> T> fmt::Debug for LazyJust<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazyJust")
+            .field("function", &"<function>")
+            .finish()
+    }
+}
+
+impl<T, F : Fn () -> T> Clone for LazyJust<T, F> {
+    fn clone(&self) -> Self {
+        LazyJust { function: self.function.clone() }
+    }
+}
+
+impl<T : fmt::Debug, F : Fn () -> T> Strategy for LazyJust<T, F> {
+    type Value = Self;
+
+    fn new_value(&self, _: &mut TestRunner) -> Result<Self::Value, String> {
+        Ok(self.clone())
+    }
+}
+
+impl<T : fmt::Debug, F : Fn () -> T> ValueTree for LazyJust<T, F> {
+    type Value = T;
+
+    fn current(&self) -> T {
+        (self.function)()
+    }
+
+    fn simplify(&mut self) -> bool { false }
+    fn complicate(&mut self) -> bool { false }
+}
+
+/// A `Strategy` which defers constructing its delegate strategy until a value
+/// is first requested.
+///
+/// See `lazy()` for more details.
+pub struct Lazy<S, F : Fn () -> S> {
+    function: Arc<F>,
+    // Shared between clones so that the delegate is built at most once.
+    built: Arc<Mutex<Option<Arc<S>>>>,
+}
+
+/// Create a strategy which calls `function` to construct its delegate
+/// strategy the first time `new_value()` is called, and then delegates to
+/// that strategy from then on.
+///
+/// This is useful when the delegate is expensive to construct and may not
+/// be needed at all, and makes it possible to write functions returning
+/// strategies which refer to themselves or to each other, since constructing
+/// the outer strategy does not immediately recurse into the inner ones.
+/// Clones of the returned strategy share the constructed delegate.
+///
+/// Note that this only defers _construction_ of the strategies; it is still
+/// the responsibility of the caller to ensure that _generation_ terminates,
+/// for example by weighting the non-recursive options heavily enough.
+/// `Strategy::prop_recursive()` is usually a better fit for simple recursive
+/// structures.
+///
+/// ## Example
+///
+/// ```
+/// #[macro_use] extern crate proptest;
+/// use proptest::prelude::*;
+/// use proptest::strategy::lazy;
+///
+/// #[derive(Clone, Debug)]
+/// enum Expr {
+///   Num(i32),
+///   Neg(Box<Expr>),
+/// }
+///
+/// // Calling `expr()` within the closure would recurse forever if the inner
+/// // strategy were built eagerly.
+/// fn expr() -> BoxedStrategy<Expr> {
+///   lazy(|| prop_oneof![
+///     3 => any_num(),
+///     1 => expr().prop_map(|e| Expr::Neg(Box::new(e))),
+///   ]).boxed()
+/// }
+///
+/// fn any_num() -> BoxedStrategy<Expr> {
+///   (0..100).prop_map(Expr::Num).boxed()
+/// }
+///
+/// proptest! {
+///   # /*
+///   #[test]
+///   # */
+///   fn test_expr(ref e in expr()) {
+///     let _ = e;
+///   }
+/// }
+/// #
+/// # fn main() { test_expr(); }
+/// ```
+pub fn lazy<S : Strategy, F : Fn () -> S>(function: F) -> Lazy<S, F> {
+    Lazy {
+        function: Arc::new(function),
+        built: Arc::new(Mutex::new(None)),
+    }
+}
+
+impl<S, F : Fn () -> S> Lazy<S, F> {
+    fn get(&self) -> Arc<S> {
+        // The lock is only held while building and cloning the `Arc`, so
+        // recursive calls from within the delegate's `new_value()` are fine.
+        // A delegate whose construction needs itself would still deadlock,
+        // but that would also be infinite recursion without the lock.
+        let mut built = self.built.lock().unwrap_or_else(|e| e.into_inner());
+        if built.is_none() {
+            *built = Some(Arc::new((self.function)()));
+        }
+        built.as_ref().unwrap().clone()
+    }
+}
+
+impl<S : fmt::Debug, F : Fn () -> S> fmt::Debug for Lazy<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let built = self.built.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_struct("Lazy")
+            .field("function", &"<function>")
+            .field("built", &*built)
+            .finish()
+    }
+}
+
+impl<S, F : Fn () -> S> Clone for Lazy<S, F> {
+    fn clone(&self) -> Self {
+        Lazy {
+            function: self.function.clone(),
+            built: self.built.clone(),
+        }
+    }
+}
+
+impl<S : Strategy, F : Fn () -> S> Strategy for Lazy<S, F> {
+    type Value = S::Value;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        self.get().new_value(runner)
+    }
+}
+
+/// A `ValueTree` which is not generated until it is actually needed.
+///
+/// This is used by `Union` and `TupleUnion` so that options other than the
+/// one initially chosen are only generated if shrinking actually falls back
+/// to them.
+pub struct LazyValueTree<S : Strategy> {
+    state: LazyValueTreeState<S>,
+}
+
+enum LazyValueTreeState<S : Strategy> {
+    Initialized(S::Value),
+    Uninitialized(Arc<S>),
+    Failed,
+}
+
+impl<S : Strategy> Clone for LazyValueTree<S> where S::Value : Clone {
+    fn clone(&self) -> Self {
+        LazyValueTree {
+            state: match self.state {
+                LazyValueTreeState::Initialized(ref v) =>
+                    LazyValueTreeState::Initialized(v.clone()),
+                LazyValueTreeState::Uninitialized(ref strategy) =>
+                    LazyValueTreeState::Uninitialized(strategy.clone()),
+                LazyValueTreeState::Failed => LazyValueTreeState::Failed,
+            },
+        }
+    }
+}
+
+impl<S : Strategy> fmt::Debug for LazyValueTree<S>
+where S::Value : fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.state {
+            LazyValueTreeState::Initialized(ref v) =>
+                f.debug_tuple("Initialized").field(v).finish(),
+            LazyValueTreeState::Uninitialized(ref strategy) =>
+                f.debug_tuple("Uninitialized").field(strategy).finish(),
+            LazyValueTreeState::Failed =>
+                f.debug_tuple("Failed").finish(),
+        }
+    }
+}
+
+impl<S : Strategy> LazyValueTree<S> {
+    /// Create a `LazyValueTree` which will generate a value from `strategy`
+    /// when `maybe_init()` is first called.
+    pub fn new(strategy: Arc<S>) -> Self {
+        LazyValueTree { state: LazyValueTreeState::Uninitialized(strategy) }
+    }
+
+    /// Create a `LazyValueTree` which is already initialised by generating a
+    /// value from `strategy` immediately.
+    pub fn new_initialized(strategy: &S, runner: &mut TestRunner)
+                           -> Result<Self, String> {
+        strategy.new_value(runner).map(
+            |v| LazyValueTree { state: LazyValueTreeState::Initialized(v) })
+    }
+
+    /// Create a `LazyValueTree` which holds the already-generated `value`.
+    pub(crate) fn from_value(value: S::Value) -> Self {
+        LazyValueTree { state: LazyValueTreeState::Initialized(value) }
+    }
+
+    /// Generate the value from the strategy using `runner` if this has not
+    /// yet been attempted.
+    ///
+    /// If generation fails, the tree is permanently marked as failed and
+    /// `is_initialized()` will return `false`.
+    pub fn maybe_init(&mut self, runner: &mut TestRunner) {
+        let strategy = match self.state {
+            LazyValueTreeState::Uninitialized(ref strategy) =>
+                strategy.clone(),
+            _ => return,
+        };
+
+        self.state = match strategy.new_value(runner) {
+            Ok(v) => LazyValueTreeState::Initialized(v),
+            Err(_) => LazyValueTreeState::Failed,
+        };
+    }
+
+    /// Returns whether this tree holds a generated value.
+    pub fn is_initialized(&self) -> bool {
+        self.as_inner().is_some()
+    }
+
+    /// Returns the generated value tree, if any.
+    pub fn as_inner(&self) -> Option<&S::Value> {
+        match self.state {
+            LazyValueTreeState::Initialized(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the generated value tree mutably, if any.
+    pub fn as_inner_mut(&mut self) -> Option<&mut S::Value> {
+        match self.state {
+            LazyValueTreeState::Initialized(ref mut v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn lazy_just_does_not_require_clone() {
+        #[derive(Debug, PartialEq)]
+        struct NotClone(u32);
+
+        let input = LazyJust::new(|| NotClone(42));
+        let mut runner = TestRunner::new(Config::default());
+        let mut case = input.new_value(&mut runner).unwrap();
+        assert_eq!(NotClone(42), case.current());
+        assert!(!case.simplify());
+        assert!(!case.complicate());
+    }
+
+    #[test]
+    fn lazy_builds_delegate_once_on_first_use() {
+        let builds = Rc::new(Cell::new(0));
+        let builds2 = builds.clone();
+        let input = lazy(move || {
+            builds2.set(builds2.get() + 1);
+            0i32..10
+        });
+        let input2 = input.clone();
+        assert_eq!(0, builds.get());
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..16 {
+            let v = input.new_value(&mut runner).unwrap().current();
+            assert!((0..10).contains(&v));
+            input2.new_value(&mut runner).unwrap();
+        }
+        assert_eq!(1, builds.get());
+    }
+
+    #[test]
+    fn lazy_allows_self_reference() {
+        fn depth() -> BoxedStrategy<u32> {
+            lazy(|| ::bool::weighted(0.25).prop_flat_map(|recurse| if recurse {
+                depth().prop_map(|d| d + 1).boxed()
+            } else {
+                Just(0u32).boxed()
+            })).boxed()
+        }
+
+        let input = depth();
+        let mut runner = TestRunner::new(Config::default());
+        let mut max = 0u32;
+        for _ in 0..256 {
+            max = ::std::cmp::max(
+                max, input.new_value(&mut runner).unwrap().current());
+        }
+        assert!(max > 0);
+    }
+}
@@ -10,6 +10,8 @@
 use std::fmt;
 use std::sync::Arc;
 
+use rand::XorShiftRng;
+
 use strategy::traits::*;
 use test_runner::*;
 
@@ -68,8 +70,99 @@ ValueTree for Map<S, F> {
     }
 }
 
+/// `Strategy` perturbation adaptor.
+///
+/// See `Strategy::prop_perturb()`.
+pub struct Perturb<S, F> {
+    pub(super) source: S,
+    pub(super) fun: Arc<F>,
+}
+
+impl<S : fmt::Debug, F> fmt::Debug for Perturb<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Perturb")
+            .field("source", &self.source)
+            .field("fun", &"<function>")
+            .finish()
+    }
+}
+
+impl<S : Clone, F> Clone for Perturb<S, F> {
+    fn clone(&self) -> Self {
+        Perturb {
+            source: self.source.clone(),
+            fun: self.fun.clone(),
+        }
+    }
+}
+
+impl<S : Strategy, O : fmt::Debug,
+     F : Fn (<S::Value as ValueTree>::Value, XorShiftRng) -> O>
+Strategy for Perturb<S, F> {
+    type Value = PerturbValueTree<S::Value, F>;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        let rng = runner.new_rng();
+
+        self.source.new_value(runner).map(|source| PerturbValueTree {
+            source, rng, fun: self.fun.clone()
+        })
+    }
+}
+
+/// `ValueTree` perturbation adaptor.
+///
+/// See `Strategy::prop_perturb()`.
+pub struct PerturbValueTree<S, F> {
+    source: S,
+    fun: Arc<F>,
+    rng: XorShiftRng,
+}
+
+impl<S : fmt::Debug, F> fmt::Debug for PerturbValueTree<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PerturbValueTree")
+            .field("source", &self.source)
+            .field("fun", &"<function>")
+            .field("rng", &"<XorShiftRng>")
+            .finish()
+    }
+}
+
+impl<S : Clone, F> Clone for PerturbValueTree<S, F> {
+    fn clone(&self) -> Self {
+        PerturbValueTree {
+            source: self.source.clone(),
+            fun: self.fun.clone(),
+            rng: self.rng.clone(),
+        }
+    }
+}
+
+impl<S : ValueTree, O : fmt::Debug, F : Fn (S::Value, XorShiftRng) -> O>
+ValueTree for PerturbValueTree<S, F> {
+    type Value = O;
+
+    fn current(&self) -> O {
+        (self.fun)(self.source.current(), self.rng.clone())
+    }
+
+    fn simplify(&mut self) -> bool {
+        self.source.simplify()
+    }
+
+    fn complicate(&mut self) -> bool {
+        self.source.complicate()
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use std::collections::HashSet;
+
+    use rand::Rng;
+
     use super::*;
 
     #[test]
@@ -80,4 +173,27 @@ mod test {
                 Ok(())
             }).unwrap();
     }
+
+    #[test]
+    fn test_perturb() {
+        let input = (0..10).prop_perturb(
+            |v, mut rng| v * 100 + rng.gen_range(0, 100));
+        let mut runner = TestRunner::new(Config::default());
+        let mut seen_noise = HashSet::new();
+
+        for _ in 0..256 {
+            let mut case = input.new_value(&mut runner).unwrap();
+            let noise = case.current() % 100;
+            seen_noise.insert(noise);
+            // Repeated calls and shrinking must reuse the same RNG state.
+            assert_eq!(case.current(), case.current());
+            while case.simplify() {
+                assert_eq!(noise, case.current() % 100);
+            }
+            assert_eq!(noise, case.current());
+        }
+
+        assert!(seen_noise.len() > 10,
+                "Only saw {} distinct perturbations", seen_noise.len());
+    }
 }
@@ -12,15 +12,21 @@
 mod traits;
 mod map;
 mod filter;
+mod filter_map;
 mod flatten;
 mod unions;
 mod recursive;
+mod mutual;
+mod lazy;
 
 pub use self::traits::*;
 pub use self::map::*;
 pub use self::filter::*;
+pub use self::filter_map::*;
 pub use self::flatten::*;
 pub use self::unions::*;
 pub use self::recursive::*;
+pub use self::mutual::*;
+pub use self::lazy::*;
 
 pub mod statics;
@@ -0,0 +1,495 @@
+//-
+// Copyright 2017 Jason Lingle
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+
+use strategy::traits::*;
+use strategy::recursive::{branch_probabilities, RecursiveLevel};
+use test_runner::*;
+
+/// Shared depth and size parameters for a set of mutually recursive
+/// strategies.
+///
+/// `Strategy::prop_recursive()` handles a single type which contains itself.
+/// When several types contain each other, such as the expressions,
+/// statements and blocks of a programming language, `MutualRecursion` can be
+/// used instead. Each type is first declared with `declare()`, which returns
+/// a `RecursiveRef` that can be passed to the definitions of the other types
+/// via `RecursiveRef::downgrade()`, and is then given its definition with
+/// `RecursiveRef::define()`.
+///
+/// `depth`, `desired_size` and `expected_branch_size` have the same meaning
+/// as for `prop_recursive()`, except that they apply to the structure as a
+/// whole. Nesting any of the declared types within any other counts as one
+/// level towards `depth`, and every branch of any type contributes to the
+/// size. Values shrink the same way as values from `prop_recursive()`.
+///
+/// The per-level strategies are built the first time each level is needed
+/// and then reused, so each definition's `recurse` function is called at most
+/// once per level.
+///
+/// The definitions of a set are kept alive by the `RecursiveRef`s returned
+/// from `declare()` and their clones. The refs captured by definitions must
+/// be obtained with `RecursiveRef::downgrade()` instead, since otherwise the
+/// definitions would keep each other alive and never be freed.
+///
+/// ## Example
+///
+/// ```
+/// #[macro_use] extern crate proptest;
+/// use proptest::prelude::*;
+/// use proptest::strategy::{MutualRecursion, RecursiveRef};
+///
+/// #[derive(Clone, Debug)]
+/// enum Expr {
+///   Num(i32),
+///   Add(Box<Expr>, Box<Expr>),
+///   Block(Box<Block>),
+/// }
+///
+/// #[derive(Clone, Debug)]
+/// enum Stmt {
+///   Nop,
+///   Expr(Expr),
+///   While(Expr, Block),
+/// }
+///
+/// #[derive(Clone, Debug)]
+/// struct Block(Vec<Stmt>);
+///
+/// fn program() -> RecursiveRef<Block> {
+///   let rec = MutualRecursion::new(6, 64, 4);
+///   let expr = rec.declare::<Expr>();
+///   let stmt = rec.declare::<Stmt>();
+///   let block = rec.declare::<Block>();
+///
+///   expr.define((0..100).prop_map(Expr::Num), {
+///     let (expr, block) = (expr.downgrade(), block.downgrade());
+///     move |level| prop_oneof![
+///       (level.element(&expr), level.element(&expr)).prop_map(
+///         |(a, b)| Expr::Add(Box::new(a), Box::new(b))),
+///       level.element(&block).prop_map(|b| Expr::Block(Box::new(b))),
+///     ].boxed()
+///   });
+///   stmt.define(Just(Stmt::Nop), {
+///     let (expr, block) = (expr.downgrade(), block.downgrade());
+///     move |level| prop_oneof![
+///       level.element(&expr).prop_map(Stmt::Expr),
+///       (level.element(&expr), level.element(&block)).prop_map(
+///         |(cond, body)| Stmt::While(cond, body)),
+///     ].boxed()
+///   });
+///   block.define(Just(Block(vec![])), {
+///     let stmt = stmt.downgrade();
+///     move |level| prop::collection::vec(level.element(&stmt), 1..4)
+///       .prop_map(Block).boxed()
+///   });
+///
+///   block
+/// }
+///
+/// proptest! {
+///   # /*
+///   #[test]
+///   # */
+///   fn test_program(ref p in program()) {
+///     let _ = p;
+///   }
+/// }
+/// #
+/// # fn main() { test_program(); }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MutualRecursion {
+    params: Rc<Params>,
+    definitions: Rc<Definitions>,
+}
+
+#[derive(Debug)]
+struct Params {
+    depth: u32,
+    branch_probabilities: Vec<f64>,
+}
+
+/// Owns the definitions of every strategy in a set.
+struct Definitions(RefCell<Vec<Rc<Any>>>);
+
+impl fmt::Debug for Definitions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Definitions")
+            .field("declared", &self.0.borrow().len())
+            .finish()
+    }
+}
+
+impl MutualRecursion {
+    /// Create a new set of mutually recursive strategies with the given size
+    /// parameters.
+    ///
+    /// See `Strategy::prop_recursive()` for the meaning of the parameters.
+    pub fn new(depth: u32, desired_size: u32, expected_branch_size: u32)
+               -> Self {
+        MutualRecursion {
+            params: Rc::new(Params {
+                depth,
+                branch_probabilities: branch_probabilities(
+                    depth, desired_size, expected_branch_size),
+            }),
+            definitions: Rc::new(Definitions(RefCell::new(Vec::new()))),
+        }
+    }
+
+    /// Declare a new strategy in this set.
+    ///
+    /// The returned `RecursiveRef` must be given a definition with
+    /// `RecursiveRef::define()` before any value is generated from it or from
+    /// another strategy referring to it.
+    pub fn declare<T : fmt::Debug + 'static>(&self) -> RecursiveRef<T> {
+        let definition: Rc<RefCell<Option<Definition<T>>>> =
+            Rc::new(RefCell::new(None));
+        self.definitions.0.borrow_mut().push(definition.clone());
+        RecursiveRef {
+            params: self.params.clone(),
+            definition: Rc::downgrade(&definition),
+            owner: Some(self.definitions.clone()),
+        }
+    }
+}
+
+/// A strategy declared by `MutualRecursion::declare()`.
+///
+/// Used as a strategy itself, this generates values using the full depth of
+/// its `MutualRecursion`. Within the definitions of the set, it is passed to
+/// `RecursionLevel::element()` to obtain the strategy for the nested level.
+///
+/// A `RecursiveRef` returned by `declare()`, and any clone of it, keeps the
+/// whole set alive. One obtained with `downgrade()` does not.
+pub struct RecursiveRef<T> {
+    params: Rc<Params>,
+    definition: Weak<RefCell<Option<Definition<T>>>>,
+    owner: Option<Rc<Definitions>>,
+}
+
+type RecurseFn<T> = Rc<Fn (&RecursionLevel) -> BoxedStrategy<T>>;
+
+struct Definition<T> {
+    base: Arc<BoxedStrategy<T>>,
+    recurse: RecurseFn<T>,
+    // The strategy with `n` levels of nesting remaining is at index `n`.
+    levels: Vec<Arc<BoxedStrategy<T>>>,
+}
+
+impl<T> fmt::Debug for RecursiveRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecursiveRef")
+            .field("params", &self.params)
+            .field("defined", &self.definition.upgrade().map(
+                |definition| definition.borrow().is_some()))
+            .field("weak", &self.owner.is_none())
+            .finish()
+    }
+}
+
+impl<T> Clone for RecursiveRef<T> {
+    fn clone(&self) -> Self {
+        RecursiveRef {
+            params: self.params.clone(),
+            definition: self.definition.clone(),
+            owner: self.owner.clone(),
+        }
+    }
+}
+
+impl<T : fmt::Debug + 'static> RecursiveRef<T> {
+    /// Return a `RecursiveRef` to the same strategy which does not keep its
+    /// set alive, for use within the definitions of the set.
+    pub fn downgrade(&self) -> Self {
+        RecursiveRef {
+            params: self.params.clone(),
+            definition: self.definition.clone(),
+            owner: None,
+        }
+    }
+
+    fn definition(&self) -> Rc<RefCell<Option<Definition<T>>>> {
+        self.definition.upgrade().expect(
+            "RecursiveRef used after its set was dropped")
+    }
+
+    /// Define this strategy.
+    ///
+    /// `base` generates the non-recursive values of this type. `recurse` is
+    /// called with a `RecursionLevel` and returns a strategy generating the
+    /// recursive values of this type, which obtains strategies for the nested
+    /// values by passing `RecursiveRef`s to `RecursionLevel::element()`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if this strategy has already been defined.
+    pub fn define<S, F>(&self, base: S, recurse: F)
+    where S : Strategy + 'static,
+          S::Value : ValueTree<Value = T>,
+          F : Fn (&RecursionLevel) -> BoxedStrategy<T> + 'static {
+        let definition = self.definition();
+        let mut definition = definition.borrow_mut();
+        assert!(definition.is_none(), "RecursiveRef defined twice");
+        *definition = Some(Definition {
+            base: Arc::new(base.boxed()),
+            recurse: Rc::new(recurse),
+            levels: Vec::new(),
+        });
+    }
+
+    /// Return the strategy for this type with `remaining` levels of nesting
+    /// left, building it and any lower levels if needed.
+    fn level(&self, remaining: u32) -> Arc<BoxedStrategy<T>> {
+        let remaining = remaining as usize;
+        let definition = self.definition();
+        loop {
+            let (next, base, recurse) = {
+                let definition = definition.borrow();
+                let definition = definition.as_ref().expect(
+                    "RecursiveRef used before being defined");
+                if let Some(strat) = definition.levels.get(remaining) {
+                    return strat.clone();
+                }
+
+                (definition.levels.len(), definition.base.clone(),
+                 definition.recurse.clone())
+            };
+
+            // Building the branch may need lower levels of this and the other
+            // strategies in the set, so the definition must not be borrowed
+            // while doing so.
+            let strat = if 0 == next {
+                base
+            } else {
+                let branch = recurse(&RecursionLevel {
+                    remaining: next as u32 - 1,
+                });
+                let level: BoxedStrategy<T> = Box::new(RecursiveLevel {
+                    leaf: self.level(next as u32 - 1),
+                    base,
+                    branch: Arc::new(branch),
+                    branch_probability: self.params.branch_probabilities[
+                        self.params.depth as usize - next],
+                    // The elements of a branch may be of any type in the set,
+                    // so they cannot be tried in its place.
+                    recorded: Rc::new(RefCell::new(Vec::new())),
+                });
+                Arc::new(level)
+            };
+
+            let mut definition = definition.borrow_mut();
+            let levels = &mut definition.as_mut().unwrap().levels;
+            if levels.len() == next {
+                levels.push(strat);
+            }
+        }
+    }
+}
+
+impl<T : fmt::Debug + 'static> Strategy for RecursiveRef<T> {
+    type Value = Box<ValueTree<Value = T>>;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        self.level(self.params.depth).new_value(runner)
+    }
+}
+
+/// Passed to the `recurse` function of each `RecursiveRef` definition to
+/// obtain strategies for the values nested one level deeper.
+#[derive(Clone, Copy, Debug)]
+pub struct RecursionLevel {
+    remaining: u32,
+}
+
+impl RecursionLevel {
+    /// Return the strategy for values of `element` nested within the branch
+    /// being defined.
+    ///
+    /// `element` must belong to the same `MutualRecursion` as the strategy
+    /// being defined.
+    pub fn element<T : fmt::Debug + 'static>(&self, element: &RecursiveRef<T>)
+                                             -> Arc<BoxedStrategy<T>> {
+        element.level(self.remaining)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::cmp::max;
+
+    use strategy::Union;
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    enum Expr {
+        Num,
+        Add(Box<Expr>, Box<Expr>),
+        Block(Box<Block>),
+    }
+
+    #[derive(Clone, Debug)]
+    enum Stmt {
+        Nop,
+        Expr(Expr),
+    }
+
+    #[derive(Clone, Debug)]
+    struct Block(Vec<Stmt>);
+
+    impl Expr {
+        fn depth(&self) -> u32 {
+            match *self {
+                Expr::Num => 0,
+                Expr::Add(ref a, ref b) => 1 + max(a.depth(), b.depth()),
+                Expr::Block(ref b) => 1 + b.depth(),
+            }
+        }
+    }
+
+    impl Stmt {
+        fn depth(&self) -> u32 {
+            match *self {
+                Stmt::Nop => 0,
+                Stmt::Expr(ref e) => 1 + e.depth(),
+            }
+        }
+    }
+
+    impl Block {
+        fn depth(&self) -> u32 {
+            if self.0.is_empty() {
+                0
+            } else {
+                1 + self.0.iter().map(Stmt::depth).max().unwrap()
+            }
+        }
+
+        fn has_nested_block(&self) -> bool {
+            fn expr(e: &Expr) -> bool {
+                match *e {
+                    Expr::Num => false,
+                    Expr::Add(ref a, ref b) => expr(a) || expr(b),
+                    Expr::Block(_) => true,
+                }
+            }
+
+            self.0.iter().any(|s| match *s {
+                Stmt::Nop => false,
+                Stmt::Expr(ref e) => expr(e),
+            })
+        }
+    }
+
+    fn program(depth: u32, calls: Rc<Cell<u32>>) -> RecursiveRef<Block> {
+        let rec = MutualRecursion::new(depth, 64, 4);
+        let expr = rec.declare::<Expr>();
+        let stmt = rec.declare::<Stmt>();
+        let block = rec.declare::<Block>();
+
+        expr.define(Just(Expr::Num), {
+            let (expr, block, calls) =
+                (expr.downgrade(), block.downgrade(), calls.clone());
+            move |level| {
+                calls.set(calls.get() + 1);
+                Union::new(vec![
+                    (level.element(&expr), level.element(&expr)).prop_map(
+                        |(a, b)| Expr::Add(Box::new(a), Box::new(b))).boxed(),
+                    level.element(&block).prop_map(
+                        |b| Expr::Block(Box::new(b))).boxed(),
+                ]).boxed()
+            }
+        });
+        stmt.define(Just(Stmt::Nop), {
+            let expr = expr.downgrade();
+            move |level| level.element(&expr).prop_map(Stmt::Expr).boxed()
+        });
+        block.define(Just(Block(vec![])), {
+            let stmt = stmt.downgrade();
+            move |level| ::collection::vec(level.element(&stmt), 1..4)
+                .prop_map(Block).boxed()
+        });
+
+        block
+    }
+
+    #[test]
+    fn mutual_recursion_respects_shared_depth() {
+        let calls = Rc::new(Cell::new(0));
+        let input = program(8, calls.clone());
+
+        let mut max_depth = 0;
+        let mut nested_blocks = 0;
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..4096 {
+            let block = input.new_value(&mut runner).unwrap().current();
+            let depth = block.depth();
+            assert!(depth <= 8, "Got depth {} for {:?}", depth, block);
+            max_depth = max(depth, max_depth);
+            if block.has_nested_block() {
+                nested_blocks += 1;
+            }
+        }
+
+        assert!(max_depth >= 3, "Only got max depth {}", max_depth);
+        assert!(nested_blocks > 0);
+        // `expr` is only built for levels 1 through 7 below the root block.
+        assert!(calls.get() <= 7, "recurse called {} times", calls.get());
+    }
+
+    #[test]
+    fn mutual_recursion_with_zero_depth_generates_base() {
+        let input = program(0, Rc::new(Cell::new(0)));
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            assert_eq!(0, input.new_value(&mut runner).unwrap().current()
+                       .depth());
+        }
+    }
+
+    #[test]
+    fn mutual_recursion_is_freed_with_last_ref() {
+        let calls = Rc::new(Cell::new(0));
+        let input = program(8, calls.clone());
+        let weak = input.downgrade();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..16 {
+            weak.new_value(&mut runner).unwrap();
+        }
+
+        assert_eq!(2, Rc::strong_count(&calls));
+        drop(input);
+        assert_eq!(1, Rc::strong_count(&calls));
+    }
+
+    #[test]
+    #[should_panic(expected = "RecursiveRef used after its set was dropped")]
+    fn weak_ref_panics_after_set_dropped() {
+        let weak = program(4, Rc::new(Cell::new(0))).downgrade();
+        let mut runner = TestRunner::new(Config::default());
+        let _ = weak.new_value(&mut runner);
+    }
+
+    #[test]
+    #[should_panic(expected = "RecursiveRef used before being defined")]
+    fn undefined_ref_panics() {
+        let rec = MutualRecursion::new(4, 16, 4);
+        let input = rec.declare::<u32>();
+        let mut runner = TestRunner::new(Config::default());
+        let _ = input.new_value(&mut runner);
+    }
+}
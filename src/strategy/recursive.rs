@@ -7,10 +7,15 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::cell::RefCell;
 use std::fmt;
+use std::rc::Rc;
 use std::sync::Arc;
 
+use rand::Rng;
+
 use strategy::traits::*;
+use strategy::lazy::LazyValueTree;
 use test_runner::*;
 
 /// Return type from `Strategy::prop_recursive()`.
@@ -54,8 +59,9 @@ Strategy for Recursive<BoxedStrategy<T>, F> {
     fn new_value(&self, runner: &mut TestRunner)
                  -> Result<Self::Value, String> {
         // Since the generator is stateless, we can't implement any "absolutely
-        // X many items" rule. We _can_, however, with extremely high
-        // probability, obtain a value near what we want by using decaying
+        // X many items" rule (`SizedRecursive` does this by taking over
+        // generation of the elements itself). We _can_, however, with extremely
+        // high probability, obtain a value near what we want by using decaying
         // probabilities of branching as we go down the tree.
         //
         // We are given a target size S and a branch size K (branch size =
@@ -82,30 +88,438 @@ Strategy for Recursive<BoxedStrategy<T>, F> {
         // with levels which are always pure branches, which further
         // underestimates size.
 
-        let mut branch_probabilities = Vec::new();
-        let mut k2 = self.expected_branch_size as u64 * 2;
-        for _ in 0..self.depth {
-            branch_probabilities.push(self.desired_size as f64 / k2 as f64);
-            k2 = k2.saturating_mul(self.expected_branch_size as u64 * 2);
-        }
+        let mut branch_probabilities = branch_probabilities(
+            self.depth, self.desired_size, self.expected_branch_size);
 
         let mut strat = self.base.clone();
         while let Some(branch_probability) = branch_probabilities.pop() {
-            let recursive_choice = Arc::new((self.recurse)(strat.clone()));
+            let recorded = Rc::new(RefCell::new(Vec::new()));
+            let element: BoxedStrategy<T> = Box::new(RecordedElement {
+                inner: strat.clone(),
+                slots: recorded.clone(),
+            });
+            let recursive_choice = Arc::new((self.recurse)(Arc::new(element)));
             let non_recursive_choice = strat;
-            strat = Arc::new(
-                ::bool::weighted(branch_probability.min(0.9))
-                    .prop_ind_flat_map(move |branch| if branch {
-                        recursive_choice.clone()
-                    } else {
-                        non_recursive_choice.clone()
-                    }).boxed());
+            let level: BoxedStrategy<T> = Box::new(RecursiveLevel {
+                base: self.base.clone(),
+                leaf: non_recursive_choice,
+                branch: recursive_choice,
+                branch_probability,
+                recorded,
+            });
+            strat = Arc::new(level);
         }
 
         strat.new_value(runner)
     }
 }
 
+/// Compute the probability of generating a branch at each level of a
+/// recursive strategy, starting from the root.
+///
+/// See the comment in `Recursive::new_value()` for how these are derived.
+pub(super) fn branch_probabilities(depth: u32, desired_size: u32,
+                        expected_branch_size: u32) -> Vec<f64> {
+    let mut branch_probabilities = Vec::new();
+    let mut k2 = expected_branch_size as u64 * 2;
+    for _ in 0..depth {
+        branch_probabilities.push(
+            (desired_size as f64 / k2 as f64).min(0.9));
+        k2 = k2.saturating_mul(expected_branch_size as u64 * 2);
+    }
+    branch_probabilities
+}
+
+/// A single level of a `Recursive` strategy, which either delegates to the
+/// strategy for the level below or generates a branch.
+pub(super) struct RecursiveLevel<T> {
+    pub(super) base: Arc<BoxedStrategy<T>>,
+    pub(super) leaf: Arc<BoxedStrategy<T>>,
+    pub(super) branch: Arc<BoxedStrategy<T>>,
+    pub(super) branch_probability: f64,
+    /// Where the element strategy `branch` was built from records the values
+    /// it generates, if it does.
+    pub(super) recorded: Rc<RefCell<Vec<Slot<T>>>>,
+}
+
+impl<T : fmt::Debug> fmt::Debug for RecursiveLevel<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecursiveLevel")
+            .field("base", &self.base)
+            .field("leaf", &self.leaf)
+            .field("branch", &self.branch)
+            .field("branch_probability", &self.branch_probability)
+            .finish()
+    }
+}
+
+impl<T : fmt::Debug + 'static> Strategy for RecursiveLevel<T> {
+    type Value = Box<ValueTree<Value = T>>;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        if runner.rng().next_f64() < self.branch_probability {
+            self.recorded.borrow_mut().clear();
+            let branch = self.branch.new_value(runner)?;
+            Ok(Box::new(CollapsibleValueTree {
+                branch,
+                base: LazyValueTree::new(self.base.clone()),
+                children: take_referenced(&self.recorded),
+                recorded: self.recorded.clone(),
+                next_child: 0,
+                runner: runner.partial_clone(),
+                state: CollapseState::Uncollapsed,
+            }))
+        } else {
+            self.leaf.new_value(runner)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CollapseState {
+    /// Collapsing to a base value has not been tried yet.
+    Uncollapsed,
+    /// The last call to `simplify()` replaced the branch with a base value.
+    Collapsed,
+    /// The base value has been accepted as the new current value.
+    Base,
+    /// The last call to `simplify()` or `complicate()` replaced the branch
+    /// with the child at the given index.
+    Promoted(usize),
+    /// The child at the given index has been accepted as the new current
+    /// value.
+    Child(usize),
+    /// Collapsing is not possible; shrink the branch itself.
+    Branch,
+}
+
+/// `ValueTree` for a branch of a `Recursive` strategy.
+///
+/// The first simplification replaces the whole branch with a freshly
+/// generated base value, which is usually much smaller. If that makes the
+/// test pass, each child of the branch is tried in its place in turn. Only if
+/// none of those fail either does shrinking move on to the branch itself.
+/// Children which only appear while shrinking the branch, such as when a
+/// union switches to a different option, are tried as soon as the branch
+/// value containing them is known to fail.
+struct CollapsibleValueTree<T : fmt::Debug + 'static> {
+    branch: Box<ValueTree<Value = T>>,
+    base: LazyValueTree<BoxedStrategy<T>>,
+    /// Every child generated for the branch so far, in order.
+    children: Vec<Slot<T>>,
+    /// Where the element strategy records the children it generates.
+    recorded: Rc<RefCell<Vec<Slot<T>>>>,
+    /// The index of the first child in `children` not yet tried.
+    next_child: usize,
+    runner: TestRunner,
+    state: CollapseState,
+}
+
+impl<T : fmt::Debug + 'static> CollapsibleValueTree<T> {
+    /// Replace the branch with the next untried child still part of its
+    /// current value, if there is one.
+    fn promote_next(&mut self) -> bool {
+        while self.next_child < self.children.len() {
+            let ix = self.next_child;
+            self.next_child += 1;
+            if Rc::strong_count(&self.children[ix]) > 1 {
+                self.state = CollapseState::Promoted(ix);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Simplify or complicate the branch itself, keeping track of any new
+    /// children generated while doing so.
+    fn shrink_branch(&mut self, simplify: bool) -> bool {
+        self.recorded.borrow_mut().clear();
+        let changed = if simplify {
+            self.branch.simplify()
+        } else {
+            self.branch.complicate()
+        };
+        self.children.extend(take_referenced(&self.recorded));
+        changed
+    }
+}
+
+impl<T : fmt::Debug + 'static> ValueTree for CollapsibleValueTree<T> {
+    type Value = T;
+
+    fn current(&self) -> T {
+        match self.state {
+            CollapseState::Collapsed | CollapseState::Base =>
+                self.base.as_inner().expect(
+                    "Collapsed without a base value").current(),
+            CollapseState::Promoted(ix) | CollapseState::Child(ix) =>
+                self.children[ix].borrow().as_ref().expect(
+                    "Empty recursive slot").current(),
+            CollapseState::Uncollapsed | CollapseState::Branch =>
+                self.branch.current(),
+        }
+    }
+
+    fn simplify(&mut self) -> bool {
+        match self.state {
+            CollapseState::Uncollapsed => {
+                self.base.maybe_init(&mut self.runner);
+                if self.base.is_initialized() {
+                    self.state = CollapseState::Collapsed;
+                    true
+                } else if self.promote_next() {
+                    true
+                } else {
+                    self.state = CollapseState::Branch;
+                    self.shrink_branch(true)
+                }
+            },
+
+            CollapseState::Collapsed => {
+                // The base value still fails, so commit to it.
+                self.state = CollapseState::Base;
+                self.base.as_inner_mut().unwrap().simplify()
+            },
+
+            CollapseState::Base =>
+                self.base.as_inner_mut().unwrap().simplify(),
+
+            CollapseState::Promoted(ix) => {
+                // The child still fails, so commit to it.
+                self.state = CollapseState::Child(ix);
+                self.children[ix].borrow_mut().as_mut().unwrap().simplify()
+            },
+
+            CollapseState::Child(ix) =>
+                self.children[ix].borrow_mut().as_mut().unwrap().simplify(),
+
+            // The current branch value fails, so any children it gained
+            // since the last attempt can be tried in its place.
+            CollapseState::Branch =>
+                self.promote_next() || self.shrink_branch(true),
+        }
+    }
+
+    fn complicate(&mut self) -> bool {
+        match self.state {
+            CollapseState::Uncollapsed => self.shrink_branch(false),
+
+            CollapseState::Collapsed | CollapseState::Promoted(_) => {
+                if !self.promote_next() {
+                    self.state = CollapseState::Branch;
+                }
+                true
+            },
+
+            CollapseState::Base =>
+                self.base.as_inner_mut().unwrap().complicate(),
+
+            CollapseState::Child(ix) =>
+                self.children[ix].borrow_mut().as_mut().unwrap().complicate(),
+
+            CollapseState::Branch => self.shrink_branch(false),
+        }
+    }
+}
+
+/// Return type from `Strategy::prop_recursive_sized()`.
+pub struct SizedRecursive<B, F> {
+    pub(super) base: Arc<B>,
+    pub(super) recurse: Arc<F>,
+    pub(super) depth: u32,
+    pub(super) max_size: u32,
+    pub(super) expected_branch_size: u32,
+}
+
+impl<B : fmt::Debug, F> fmt::Debug for SizedRecursive<B, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SizedRecursive")
+            .field("base", &self.base)
+            .field("recurse", &"<function>")
+            .field("depth", &self.depth)
+            .field("max_size", &self.max_size)
+            .field("expected_branch_size", &self.expected_branch_size)
+            .finish()
+    }
+}
+
+impl<B, F> Clone for SizedRecursive<B, F> {
+    fn clone(&self) -> Self {
+        SizedRecursive {
+            base: self.base.clone(),
+            recurse: self.recurse.clone(),
+            depth: self.depth,
+            max_size: self.max_size,
+            expected_branch_size: self.expected_branch_size,
+        }
+    }
+}
+
+pub(super) type Slot<T> = Rc<RefCell<Option<Box<ValueTree<Value = T>>>>>;
+
+/// The element strategy passed to the `recurse` function of a `Recursive` or
+/// `SizedRecursive`.
+///
+/// The slot holding each value it generates is recorded, so that the
+/// children of a branch can be tried in its place while shrinking. For a
+/// `SizedRecursive`, every value is initially a leaf from the base strategy;
+/// once the enclosing branch is fully generated and the number of elements is
+/// known, the remaining size budget is divided among the elements and some of
+/// them grown into branches.
+struct RecordedElement<T> {
+    inner: Arc<BoxedStrategy<T>>,
+    slots: Rc<RefCell<Vec<Slot<T>>>>,
+}
+
+impl<T> fmt::Debug for RecordedElement<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecordedElement")
+            .field("pending", &self.slots.borrow().len())
+            .finish()
+    }
+}
+
+impl<T : fmt::Debug + 'static> Strategy for RecordedElement<T> {
+    type Value = Box<ValueTree<Value = T>>;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        let slot = Rc::new(RefCell::new(Some(self.inner.new_value(runner)?)));
+        self.slots.borrow_mut().push(slot.clone());
+        Ok(Box::new(SlotValueTree(slot)))
+    }
+}
+
+/// Take the slots recorded in `slots`, keeping only those still referenced by
+/// a `SlotValueTree`, i.e., excluding values which were generated and then
+/// discarded, such as by a filter.
+fn take_referenced<T>(slots: &RefCell<Vec<Slot<T>>>) -> Vec<Slot<T>> {
+    slots.replace(Vec::new()).into_iter()
+        .filter(|slot| Rc::strong_count(slot) > 1)
+        .collect()
+}
+
+/// `ValueTree` for a single element produced by `RecordedElement`.
+struct SlotValueTree<T>(Slot<T>);
+
+impl<T : fmt::Debug> ValueTree for SlotValueTree<T> {
+    type Value = T;
+
+    fn current(&self) -> T {
+        self.0.borrow().as_ref().expect("Empty recursive slot").current()
+    }
+
+    fn simplify(&mut self) -> bool {
+        self.0.borrow_mut().as_mut().expect("Empty recursive slot").simplify()
+    }
+
+    fn complicate(&mut self) -> bool {
+        self.0.borrow_mut().as_mut().expect("Empty recursive slot")
+            .complicate()
+    }
+}
+
+impl<T : fmt::Debug + 'static,
+     F : Fn (Arc<BoxedStrategy<T>>, u32) -> BoxedStrategy<T>>
+SizedRecursive<BoxedStrategy<T>, F> {
+    /// Generate a branch at `level` whose descendants may have at most
+    /// `budget` nodes in total. `leaf` is what the branch collapses to when
+    /// shrinking.
+    fn new_branch(&self, runner: &mut TestRunner,
+                  probabilities: &[f64], level: usize, budget: u32,
+                  leaf: LazyValueTree<BoxedStrategy<T>>)
+                  -> Result<Box<ValueTree<Value = T>>, String> {
+        let recorded = Rc::new(RefCell::new(Vec::new()));
+        let element: BoxedStrategy<T> = Box::new(RecordedElement {
+            inner: self.base.clone(),
+            slots: recorded.clone(),
+        });
+        let branch = (self.recurse)(Arc::new(element), budget)
+            .new_value(runner)?;
+
+        // Elements which were generated and then discarded, such as by a
+        // filter within `recurse`, are not part of the branch.
+        let slots = take_referenced(&recorded);
+        if slots.len() as u64 > budget as u64 {
+            return Err(format!(
+                "prop_recursive_sized: recurse produced {} elements with a \
+                 budget of only {}", slots.len(), budget));
+        }
+
+        // Every element already costs one node as a leaf. Decide which ones
+        // should grow into branches, then hand out what is left of the budget
+        // among those.
+        let mut remaining = budget - slots.len() as u32;
+        let mut growing = Vec::new();
+        if level + 1 < probabilities.len() {
+            for slot in &slots {
+                if runner.rng().next_f64() < probabilities[level + 1] {
+                    growing.push((slot.clone(), 0u32));
+                }
+            }
+        }
+        if !growing.is_empty() {
+            while remaining > 0 {
+                let ix = runner.rng().gen_range(0, growing.len());
+                growing[ix].1 += 1;
+                remaining -= 1;
+            }
+        }
+
+        for (slot, child_budget) in growing {
+            if child_budget > 0 {
+                let leaf = slot.borrow_mut().take().unwrap();
+                let child = self.new_branch(
+                    runner, probabilities, level + 1, child_budget,
+                    LazyValueTree::from_value(leaf));
+                match child {
+                    Ok(child) => *slot.borrow_mut() = Some(child),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Ok(Box::new(CollapsibleValueTree {
+            branch,
+            base: leaf,
+            children: slots,
+            recorded,
+            next_child: 0,
+            runner: runner.partial_clone(),
+            state: CollapseState::Uncollapsed,
+        }))
+    }
+}
+
+impl<T : fmt::Debug + 'static,
+     F : Fn (Arc<BoxedStrategy<T>>, u32) -> BoxedStrategy<T>>
+Strategy for SizedRecursive<BoxedStrategy<T>, F> {
+    type Value = Box<ValueTree<Value = T>>;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        if 0 == self.max_size {
+            return Err("prop_recursive_sized: max_size must be at least 1"
+                       .to_owned());
+        }
+
+        // Level 0 is the root, which may branch if `depth` is at least 1;
+        // `probabilities[l]` is the chance of a node at level `l` branching.
+        let probabilities = branch_probabilities(
+            self.depth, self.max_size, self.expected_branch_size);
+
+        if self.max_size > 1 && !probabilities.is_empty() &&
+            runner.rng().next_f64() < probabilities[0]
+        {
+            self.new_branch(runner, &probabilities, 0, self.max_size - 1,
+                            LazyValueTree::new(self.base.clone()))
+        } else {
+            self.base.new_value(runner)
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::cmp::max;
@@ -161,4 +575,204 @@ mod test {
         assert!(max_depth >= 3, "Only got max depth {}", max_depth);
         assert!(max_count > 48, "Only got max count {}", max_count);
     }
+
+    #[test]
+    fn shrinks_deep_trees_to_minimal_depth() {
+        #[derive(Clone, Debug)]
+        enum Tree {
+            Leaf,
+            Branch(Vec<Tree>),
+        }
+
+        impl Tree {
+            fn stats(&self) -> (u32, u32) {
+                match *self {
+                    Tree::Leaf => (0, 1),
+                    Tree::Branch(ref children) => {
+                        let mut depth = 0;
+                        let mut count = 0;
+                        for child in children {
+                            let (d, c) = child.stats();
+                            depth = max(d, depth);
+                            count += c;
+                        }
+
+                        (depth + 1, count + 1)
+                    }
+                }
+            }
+        }
+
+        let strat = Just(Tree::Leaf).prop_recursive(
+            6, 256, 8,
+            |element| ::collection::vec(element, 1..8)
+                .prop_map(Tree::Branch).boxed());
+
+        let mut failures = 0;
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let case = strat.new_value(&mut runner).unwrap();
+            let result = runner.run_one(case, |tree| {
+                if tree.stats().0 < 2 {
+                    Ok(())
+                } else {
+                    Err(TestCaseError::Fail("too deep".to_owned()))
+                }
+            });
+
+            match result {
+                Ok(_) => (),
+                Err(TestError::Fail(_, tree)) => {
+                    failures += 1;
+                    let (depth, count) = tree.stats();
+                    assert_eq!(2, depth, "Bad depth for {:?}", tree);
+                    assert_eq!(3, count, "Bad count for {:?}", tree);
+                },
+                e => panic!("Unexpected result: {:?}", e),
+            }
+        }
+
+        assert!(failures > 16, "Only {} failures", failures);
+    }
+
+    #[test]
+    fn shrinks_to_failing_inner_branch() {
+        #[derive(Clone, Debug, PartialEq)]
+        enum Expr {
+            Lit,
+            Add(Box<Expr>, Box<Expr>),
+            Mul(Box<Expr>, Box<Expr>),
+        }
+
+        impl Expr {
+            fn has_mul(&self) -> bool {
+                match *self {
+                    Expr::Lit => false,
+                    Expr::Add(ref a, ref b) => a.has_mul() || b.has_mul(),
+                    Expr::Mul(..) => true,
+                }
+            }
+        }
+
+        let strat = Just(Expr::Lit).prop_recursive(
+            4, 32, 2,
+            |element| ::strategy::Union::new(vec![
+                (element.clone(), element.clone()).prop_map(
+                    |(a, b)| Expr::Add(Box::new(a), Box::new(b))).boxed(),
+                (element.clone(), element).prop_map(
+                    |(a, b)| Expr::Mul(Box::new(a), Box::new(b))).boxed(),
+            ]).boxed());
+
+        let mut failures = 0;
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let case = strat.new_value(&mut runner).unwrap();
+            // The failure only depends on a `Mul` somewhere in the tree, which
+            // the top-level `Add` branches can only be shrunk away from by
+            // replacing them with their children.
+            let result = runner.run_one(case, |expr| {
+                if expr.has_mul() {
+                    Err(TestCaseError::Fail("has mul".to_owned()))
+                } else {
+                    Ok(())
+                }
+            });
+
+            match result {
+                Ok(_) => (),
+                Err(TestError::Fail(_, expr)) => {
+                    failures += 1;
+                    assert_eq!(Expr::Mul(Box::new(Expr::Lit),
+                                         Box::new(Expr::Lit)), expr);
+                },
+                e => panic!("Unexpected result: {:?}", e),
+            }
+        }
+
+        assert!(failures > 16, "Only {} failures", failures);
+    }
+
+    #[test]
+    fn sized_recursive_never_exceeds_budget() {
+        #[derive(Clone, Debug)]
+        enum Tree {
+            Leaf,
+            Branch(Vec<Tree>),
+        }
+
+        impl Tree {
+            fn stats(&self) -> (u32, u32) {
+                match *self {
+                    Tree::Leaf => (0, 1),
+                    Tree::Branch(ref children) => {
+                        let mut depth = 0;
+                        let mut count = 0;
+                        for child in children {
+                            let (d, c) = child.stats();
+                            depth = max(d, depth);
+                            count += c;
+                        }
+
+                        (depth + 1, count + 1)
+                    }
+                }
+            }
+        }
+
+        let mut max_depth = 0;
+        let mut max_count = 0;
+
+        let strat = Just(Tree::Leaf).prop_recursive_sized(
+            4, 20, 8,
+            |element, available| ::collection::vec(
+                element, 1..::std::cmp::min(8, available as usize) + 1)
+                .prop_map(Tree::Branch).boxed());
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..4096 {
+            let mut case = strat.new_value(&mut runner).unwrap();
+            let (depth, count) = case.current().stats();
+            assert!(depth <= 4, "Got depth {}", depth);
+            assert!(count <= 20, "Got count {}", count);
+            max_depth = max(depth, max_depth);
+            max_count = max(count, max_count);
+
+            while case.simplify() {
+                assert!(case.current().stats().1 <= 20);
+            }
+        }
+
+        assert!(max_depth >= 3, "Only got max depth {}", max_depth);
+        assert!(max_count >= 16, "Only got max count {}", max_count);
+    }
+
+    #[test]
+    fn sized_recursive_ignores_filtered_elements() {
+        let strat = (0..10u32).prop_recursive_sized(
+            3, 16, 4,
+            |element, available| ::collection::vec(
+                element.prop_filter("odd".to_owned(), |&v| 0 == v % 2),
+                available as usize..available as usize + 1)
+                .prop_map(|v| v.len() as u32).boxed());
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            strat.new_value(&mut runner).unwrap();
+        }
+    }
+
+    #[test]
+    fn sized_recursive_rejects_overspending_recurse() {
+        let strat = Just(0u32).prop_recursive_sized(
+            2, 4, 8,
+            |element, _| ::collection::vec(element, 8..9)
+                .prop_map(|v| v.len() as u32).boxed());
+
+        let mut runner = TestRunner::new(Config::default());
+        let mut failed = false;
+        for _ in 0..64 {
+            failed |= strat.new_value(&mut runner).is_err();
+        }
+        assert!(failed);
+    }
 }
@@ -10,6 +10,8 @@
 use std::fmt;
 use std::sync::Arc;
 
+use rand::XorShiftRng;
+
 use strategy::*;
 use test_runner::*;
 
@@ -47,6 +49,45 @@ pub trait Strategy : fmt::Debug {
         Map { source: self, fun: Arc::new(fun) }
     }
 
+    /// Returns a strategy which produces values transformed by the function
+    /// `fun`, which is additionally given a random number generator.
+    ///
+    /// This is exactly like `prop_map()` except for the addition of the second
+    /// argument to the function. This allows introducing chaotic variations to
+    /// generated values that are not easily expressed otherwise while allowing
+    /// shrinking to proceed reasonably.
+    ///
+    /// During shrinking, `fun` is always called with an identical random
+    /// number generator, so if it is a pure function it will always perform
+    /// the same perturbation.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// #[macro_use] extern crate proptest;
+    /// // The prelude also gets us the `Rng` trait.
+    /// use proptest::prelude::*;
+    ///
+    /// proptest! {
+    ///   # /*
+    ///   #[test]
+    ///   # */
+    ///   fn test_something(a in (0..100).prop_perturb(
+    ///     |v, mut rng| v * 2 + rng.gen_range(0, 2)
+    ///   )) {
+    ///     prop_assert!(a >= 0 && a < 200);
+    ///   }
+    /// }
+    /// #
+    /// # fn main() { test_something(); }
+    /// ```
+    fn prop_perturb<O : fmt::Debug,
+                    F : Fn (<Self::Value as ValueTree>::Value, XorShiftRng) -> O>
+        (self, fun: F) -> Perturb<Self, F>
+    where Self : Sized {
+        Perturb { source: self, fun: Arc::new(fun) }
+    }
+
     /// Maps values produced by this strategy into new strategies and picks
     /// values from those strategies.
     ///
@@ -202,6 +243,27 @@ pub trait Strategy : fmt::Debug {
         Filter { source: self, whence: whence, fun: Arc::new(fun) }
     }
 
+    /// Returns a strategy which produces values transformed by the function
+    /// `fun`, discarding any values for which `fun` returns `None`.
+    ///
+    /// This is equivalent to chaining `prop_map()`, `prop_filter()` and
+    /// another `prop_map()` to unwrap the `Option`, but only evaluates `fun`
+    /// once per source value and reports rejections under `whence`. All the
+    /// caveats of `prop_filter()` regarding rejection sampling apply here as
+    /// well.
+    ///
+    /// During shrinking, simplified source values for which `fun` returns
+    /// `None` are skipped in the same way that `prop_filter()` skips rejected
+    /// values.
+    ///
+    /// `whence` is used to record where and why the rejection occurred.
+    fn prop_filter_map<O : fmt::Debug,
+                       F : Fn (<Self::Value as ValueTree>::Value) -> Option<O>>
+        (self, whence: String, fun: F) -> FilterMap<Self, F>
+    where Self : Sized {
+        FilterMap { source: self, whence, fun: Arc::new(fun) }
+    }
+
     /// Returns a strategy which picks uniformly from `self` and `other`.
     ///
     /// When shrinking, if a value from `other` was originally chosen but that
@@ -241,6 +303,16 @@ pub trait Strategy : fmt::Debug {
     /// `expected_branch_size` (though it is not a hard limit) since the
     /// underlying code underestimates probabilities.
     ///
+    /// ## Shrinking
+    ///
+    /// When shrinking, each branch first tries to replace itself wholesale
+    /// with a newly generated value from `self`, and then with each of the
+    /// values it was built from by `element`. Only if all of those make the
+    /// test pass does shrinking proceed within the branch, where the same is
+    /// tried for each nested branch in turn. This means that failing deep
+    /// structures usually shrink down to only the few branches actually
+    /// needed to reproduce the failure.
+    ///
     /// ## Example
     ///
     /// ```rust,norun
@@ -299,6 +371,82 @@ pub trait Strategy : fmt::Debug {
         }
     }
 
+    /// Like `prop_recursive()`, but with a hard limit on the total number of
+    /// nodes in each generated structure.
+    ///
+    /// Every leaf and every branch counts as one node, and no generated value
+    /// ever has more than `max_size` nodes or a depth greater than `depth`.
+    /// `expected_branch_size` is used as in `prop_recursive()` to pick how
+    /// likely each level is to branch.
+    ///
+    /// In addition to the element strategy, `recurse` is passed the number of
+    /// nodes still available below the branch being generated. Each value kept
+    /// from the element strategy costs at least one node, so `recurse` must not
+    /// use more elements than that, for example by using it as the upper bound
+    /// of a collection size. Doing so fails generation with an error. Elements
+    /// which are drawn and then discarded, such as by a filter, do not count.
+    /// Whatever is left of the budget after the branch's own elements is
+    /// divided among the elements which become branches themselves.
+    ///
+    /// The element strategy only has meaning while its branch is being
+    /// generated, and must not be stored or used from another thread.
+    ///
+    /// Shrinking works the same as for `prop_recursive()`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// #[macro_use] extern crate proptest;
+    /// use proptest::prelude::*;
+    ///
+    /// #[derive(Clone, Debug)]
+    /// enum Tree {
+    ///   Leaf(u8),
+    ///   Node(Vec<Tree>),
+    /// }
+    ///
+    /// impl Tree {
+    ///   fn size(&self) -> usize {
+    ///     match *self {
+    ///       Tree::Leaf(_) => 1,
+    ///       Tree::Node(ref children) =>
+    ///         1 + children.iter().map(Tree::size).sum::<usize>(),
+    ///     }
+    ///   }
+    /// }
+    ///
+    /// fn tree() -> BoxedStrategy<Tree> {
+    ///   prop::num::u8::ANY.prop_map(Tree::Leaf).prop_recursive_sized(
+    ///     4, 32, 8,
+    ///     |element, available| prop::collection::vec(
+    ///       element, 1..::std::cmp::min(8, available as usize) + 1)
+    ///       .prop_map(Tree::Node).boxed()).boxed()
+    /// }
+    ///
+    /// proptest! {
+    ///   # /*
+    ///   #[test]
+    ///   # */
+    ///   fn never_exceeds_budget(ref t in tree()) {
+    ///     prop_assert!(t.size() <= 32);
+    ///   }
+    /// }
+    /// #
+    /// # fn main() { never_exceeds_budget(); }
+    /// ```
+    fn prop_recursive_sized<
+            F : Fn (Arc<BoxedStrategy<<Self::Value as ValueTree>::Value>>, u32)
+                    -> BoxedStrategy<<Self::Value as ValueTree>::Value>>
+        (self, depth: u32, max_size: u32, expected_branch_size: u32, recurse: F)
+        -> SizedRecursive<BoxedStrategy<<Self::Value as ValueTree>::Value>, F>
+    where Self : Sized + 'static {
+        SizedRecursive {
+            base: Arc::new(self.boxed()),
+            recurse: Arc::new(recurse),
+            depth, max_size, expected_branch_size,
+        }
+    }
+
     /// Erases the type of this `Strategy` so it can be passed around as a
     /// simple trait object.
     fn boxed(self) -> BoxedStrategy<<Self::Value as ValueTree>::Value>
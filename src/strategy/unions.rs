@@ -8,12 +8,15 @@
 // except according to those terms.
 
 use std::cmp::{max, min};
+use std::fmt;
+use std::sync::Arc;
 use std::u32;
 
 use rand;
 use rand::distributions::IndependentSample;
 
 use strategy::traits::*;
+use strategy::lazy::LazyValueTree;
 use test_runner::*;
 
 /// A `Strategy` which picks from one of several delegate `Stragegy`s.
@@ -21,7 +24,7 @@ use test_runner::*;
 /// See `Strategy::prop_union()`.
 #[derive(Clone, Debug)]
 pub struct Union<T : Strategy> {
-    options: Vec<(u32,T)>,
+    options: Vec<(u32,Arc<T>)>,
 }
 
 impl<T : Strategy> Union<T> {
@@ -30,14 +33,15 @@ impl<T : Strategy> Union<T> {
     ///
     /// When shrinking, after maximal simplification of the chosen element, the
     /// strategy will move to earlier options and continue simplification with
-    /// those.
+    /// those. Earlier options are only generated once shrinking reaches them;
+    /// any which fail to generate are skipped.
     ///
     /// ## Panics
     ///
     /// Panics if `options` is empty.
     pub fn new<I : IntoIterator<Item = T>>(options: I) -> Self {
-        let options: Vec<(u32,T)> = options.into_iter()
-            .map(|v| (1, v)).collect();
+        let options: Vec<(u32,Arc<T>)> = options.into_iter()
+            .map(|v| (1, Arc::new(v))).collect();
         assert!(options.len() > 0);
 
         Union { options }
@@ -61,12 +65,14 @@ impl<T : Strategy> Union<T> {
                 "Union option has a weight of 0");
         assert!(options.iter().map(|&(w, _)| w as u64).sum::<u64>() <=
                 u32::MAX as u64, "Union weights overflow u32");
+        let options = options.into_iter()
+            .map(|(w, v)| (w, Arc::new(v))).collect();
         Union { options }
     }
 
     /// Add `other` as an additional alternate strategy with weight 1.
     pub fn or(mut self, other: T) -> Self {
-        self.options.push((1, other));
+        self.options.push((1, Arc::new(other)));
         self
     }
 }
@@ -83,7 +89,7 @@ fn pick_weighted<I : Iterator<Item = u32>>(runner: &mut TestRunner,
 }
 
 impl<T : Strategy> Strategy for Union<T> {
-    type Value = UnionValueTree<T::Value>;
+    type Value = UnionValueTree<T>;
 
     fn new_value(&self, runner: &mut TestRunner)
                  -> Result<Self::Value, String> {
@@ -91,17 +97,22 @@ impl<T : Strategy> Strategy for Union<T> {
 
         let pick = pick_weighted(
             runner,
-            self.options.iter().map(extract_weight::<T>),
-            self.options.iter().map(extract_weight::<T>));
-
-        let mut options = Vec::with_capacity(pick);
-        for option in &self.options[0..pick+1] {
-            options.push(option.1.new_value(runner)?);
+            self.options.iter().map(extract_weight::<Arc<T>>),
+            self.options.iter().map(extract_weight::<Arc<T>>));
+
+        // Only the chosen option is generated now; the ones before it are
+        // only generated if shrinking falls back to them.
+        let mut options = Vec::with_capacity(pick + 1);
+        for option in &self.options[0..pick] {
+            options.push(LazyValueTree::new(option.1.clone()));
         }
+        options.push(LazyValueTree::new_initialized(
+            &*self.options[pick].1, runner)?);
 
         Ok(UnionValueTree {
-            options: options,
-            pick: pick,
+            options,
+            runner: runner.partial_clone(),
+            pick,
             min_pick: 0,
             prev_pick: None,
         })
@@ -109,42 +120,88 @@ impl<T : Strategy> Strategy for Union<T> {
 }
 
 /// `ValueTree` corresponding to `Union`.
-#[derive(Clone, Debug)]
-pub struct UnionValueTree<T : ValueTree> {
-    options: Vec<T>,
+pub struct UnionValueTree<T : Strategy> {
+    options: Vec<LazyValueTree<T>>,
+    // Used to generate the options which were not chosen initially if
+    // shrinking moves on to them.
+    runner: TestRunner,
     pick: usize,
     min_pick: usize,
     prev_pick: Option<usize>,
 }
 
+impl<T : Strategy> Clone for UnionValueTree<T> where T::Value : Clone {
+    fn clone(&self) -> Self {
+        UnionValueTree {
+            options: self.options.clone(),
+            runner: self.runner.clone(),
+            pick: self.pick,
+            min_pick: self.min_pick,
+            prev_pick: self.prev_pick,
+        }
+    }
+}
+
+impl<T : Strategy> fmt::Debug for UnionValueTree<T>
+where T::Value : fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UnionValueTree")
+            .field("options", &self.options)
+            .field("runner", &self.runner)
+            .field("pick", &self.pick)
+            .field("min_pick", &self.min_pick)
+            .field("prev_pick", &self.prev_pick)
+            .finish()
+    }
+}
+
 macro_rules! access_vec {
     ([$($muta:tt)*] $dst:ident = $this:expr, $ix:expr, $body:block) => {{
-        let $dst = &$($muta)* $this.options[$ix];
+        let $dst = $this.options[$ix].$($muta)*.expect(
+            "Union picked an uninitialised option");
         $body
     }}
 }
 
+macro_rules! init_vec {
+    ($this:expr, $ix:expr) => {{
+        let opt = &mut $this.options[$ix];
+        opt.maybe_init(&mut $this.runner);
+        opt.is_initialized()
+    }}
+}
+
 macro_rules! union_value_tree_body {
-    ($typ:ty, $access:ident) => {
+    ($typ:ty, $access:ident, $init:ident) => {
         type Value = $typ;
 
         fn current(&self) -> Self::Value {
-            $access!([] opt = self, self.pick, {
+            $access!([as_inner()] opt = self, self.pick, {
                 opt.current()
             })
         }
 
         fn simplify(&mut self) -> bool {
-            if $access!([mut] opt = self, self.pick, { opt.simplify() }) {
+            if $access!([as_inner_mut()] opt = self, self.pick,
+                        { opt.simplify() }) {
                 self.prev_pick = None;
-                true
-            } else if self.pick > self.min_pick {
-                self.prev_pick = Some(self.pick);
-                self.pick -= 1;
-                true
-            } else {
-                false
+                return true;
+            }
+
+            // Move on to the nearest earlier option which can actually be
+            // generated, generating it now if this is the first time we
+            // reach it.
+            let mut next_pick = self.pick;
+            while next_pick > self.min_pick {
+                next_pick -= 1;
+                if $init!(self, next_pick) {
+                    self.prev_pick = Some(self.pick);
+                    self.pick = next_pick;
+                    return true;
+                }
             }
+
+            false
         }
 
         fn complicate(&mut self) -> bool {
@@ -154,35 +211,41 @@ macro_rules! union_value_tree_body {
                 self.prev_pick = None;
                 true
             } else {
-                $access!([mut] opt = self, self.pick, { opt.complicate() })
+                $access!([as_inner_mut()] opt = self, self.pick,
+                         { opt.complicate() })
             }
         }
     }
 }
 
-impl<T : ValueTree> ValueTree for UnionValueTree<T> {
-    union_value_tree_body!(T::Value, access_vec);
+impl<T : Strategy> ValueTree for UnionValueTree<T> {
+    union_value_tree_body!(<T::Value as ValueTree>::Value, access_vec, init_vec);
 }
 
 macro_rules! def_access_tuple {
-    ($b:tt $name:ident, $($n:tt)*) => {
+    ($b:tt $name:ident $init:ident, $($n:tt)*) => {
         macro_rules! $name {
             ([$b($b muta:tt)*] $b dst:ident = $b this:expr,
              $b ix:expr, $b body:block) => {
                 match $b ix {
-                    0 => {
-                        let $b dst = &$b($b muta)* $b this.options.0;
-                        $b body
-                    },
                     $(
                         $n => {
-                            if let Some(ref $b($b muta)* $b dst) =
-                                $b this.options.$n
-                            {
-                                $b body
-                            } else {
-                                unreachable!()
-                            }
+                            let $b dst = $b this.options.$n.$b($b muta)*
+                                .expect("Union picked an uninitialised option");
+                            $b body
+                        },
+                    )*
+                    _ => unreachable!() }
+            }
+        }
+
+        macro_rules! $init {
+            ($b this:expr, $b ix:expr) => {
+                match $b ix {
+                    $(
+                        $n => {
+                            $b this.options.$n.maybe_init(&mut $b this.runner);
+                            $b this.options.$n.is_initialized()
                         },
                     )*
                     _ => unreachable!() }
@@ -191,15 +254,15 @@ macro_rules! def_access_tuple {
     }
 }
 
-def_access_tuple!($ access_tuple2, 1);
-def_access_tuple!($ access_tuple3, 1 2);
-def_access_tuple!($ access_tuple4, 1 2 3);
-def_access_tuple!($ access_tuple5, 1 2 3 4);
-def_access_tuple!($ access_tuple6, 1 2 3 4 5);
-def_access_tuple!($ access_tuple7, 1 2 3 4 5 6);
-def_access_tuple!($ access_tuple8, 1 2 3 4 5 6 7);
-def_access_tuple!($ access_tuple9, 1 2 3 4 5 6 7 8);
-def_access_tuple!($ access_tupleA, 1 2 3 4 5 6 7 8 9);
+def_access_tuple!($ access_tuple2 init_tuple2, 0 1);
+def_access_tuple!($ access_tuple3 init_tuple3, 0 1 2);
+def_access_tuple!($ access_tuple4 init_tuple4, 0 1 2 3);
+def_access_tuple!($ access_tuple5 init_tuple5, 0 1 2 3 4);
+def_access_tuple!($ access_tuple6 init_tuple6, 0 1 2 3 4 5);
+def_access_tuple!($ access_tuple7 init_tuple7, 0 1 2 3 4 5 6);
+def_access_tuple!($ access_tuple8 init_tuple8, 0 1 2 3 4 5 6 7);
+def_access_tuple!($ access_tuple9 init_tuple9, 0 1 2 3 4 5 6 7 8);
+def_access_tuple!($ access_tupleA init_tupleA, 0 1 2 3 4 5 6 7 8 9);
 
 /// Similar to `Union`, but internally uses a tuple to hold the strategies.
 ///
@@ -213,7 +276,7 @@ impl<T> TupleUnion<T> {
     /// Wrap `tuple` in a `TupleUnion`.
     ///
     /// The struct definition allows any `T` for `tuple`, but to be useful, it
-    /// must be a 2- to 10-tuple of `(u32, impl Strategy)` pairs where all
+    /// must be a 2- to 10-tuple of `(u32, Arc<impl Strategy>)` pairs where all
     /// strategies ultimately produce the same value. Each `u32` indicates the
     /// relative weight of its corresponding strategy.
     ///
@@ -226,12 +289,22 @@ impl<T> TupleUnion<T> {
 
 macro_rules! tuple_union {
     ($($gen:ident $ix:tt)*) => {
+        impl<A, $($gen),*> TupleUnion<((u32, Arc<A>), $((u32, Arc<$gen>)),*)> {
+            /// Returns the sum of the weights of all options.
+            ///
+            /// This is used by `prop_oneof!` to weight nested unions.
+            #[doc(hidden)]
+            pub fn total_weight(&self) -> u32 {
+                ((self.0).0).0 $(+ ((self.0).$ix).0)*
+            }
+        }
+
         impl<A : Strategy, $($gen: Strategy),*> Strategy
-        for TupleUnion<((u32, A), $((u32, $gen)),*)>
+        for TupleUnion<((u32, Arc<A>), $((u32, Arc<$gen>)),*)>
         where $($gen::Value : ValueTree<Value =
                 <A::Value as ValueTree>::Value>),* {
             type Value = TupleUnionValueTree<
-                (A::Value, $(Option<$gen::Value>),*)>;
+                (LazyValueTree<A>, $(LazyValueTree<$gen>),*)>;
 
             fn new_value(&self, runner: &mut TestRunner)
                          -> Result<Self::Value, String> {
@@ -239,15 +312,23 @@ macro_rules! tuple_union {
                 let pick = pick_weighted(runner, weights.iter().cloned(),
                                          weights.iter().cloned());
 
+                let mut options = (
+                    LazyValueTree::new(((self.0).0).1.clone()),
+                    $(LazyValueTree::new(((self.0).$ix).1.clone())),*);
+                match pick {
+                    0 => options.0 = LazyValueTree::new_initialized(
+                        &*((self.0).0).1, runner)?,
+                    $(
+                        $ix => options.$ix = LazyValueTree::new_initialized(
+                            &*((self.0).$ix).1, runner)?,
+                    )*
+                    _ => unreachable!(),
+                }
+
                 Ok(TupleUnionValueTree {
-                    options: (
-                        ((self.0).0).1.new_value(runner)?,
-                        $(if pick <= $ix {
-                            Some(((self.0).$ix).1.new_value(runner)?)
-                        } else {
-                            None
-                        }),*),
-                    pick: pick,
+                    options,
+                    runner: runner.partial_clone(),
+                    pick,
                     min_pick: 0,
                     prev_pick: None,
                 })
@@ -267,32 +348,38 @@ tuple_union!(B 1 C 2 D 3 E 4 F 5 G 6 H 7 I 8);
 tuple_union!(B 1 C 2 D 3 E 4 F 5 G 6 H 7 I 8 J 9);
 
 /// `ValueTree` type produced by `TupleUnion`.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct TupleUnionValueTree<T> {
     options: T,
+    // Used to generate the options which were not chosen initially if
+    // shrinking moves on to them.
+    runner: TestRunner,
     pick: usize,
     min_pick: usize,
     prev_pick: Option<usize>,
 }
 
 macro_rules! value_tree_tuple {
-    ($access:ident, $($gen:ident)*) => {
-        impl<A : ValueTree, $($gen: ValueTree<Value = A::Value>),*> ValueTree
-        for TupleUnionValueTree<(A, $(Option<$gen>),*)> {
-            union_value_tree_body!(A::Value, $access);
+    ($access:ident $init:ident, $($gen:ident)*) => {
+        impl<A : Strategy, $($gen: Strategy),*> ValueTree
+        for TupleUnionValueTree<(LazyValueTree<A>, $(LazyValueTree<$gen>),*)>
+        where $($gen::Value : ValueTree<Value =
+                <A::Value as ValueTree>::Value>),* {
+            union_value_tree_body!(<A::Value as ValueTree>::Value,
+                                   $access, $init);
         }
     }
 }
 
-value_tree_tuple!(access_tuple2, B);
-value_tree_tuple!(access_tuple3, B C);
-value_tree_tuple!(access_tuple4, B C D);
-value_tree_tuple!(access_tuple5, B C D E);
-value_tree_tuple!(access_tuple6, B C D E F);
-value_tree_tuple!(access_tuple7, B C D E F G);
-value_tree_tuple!(access_tuple8, B C D E F G H);
-value_tree_tuple!(access_tuple9, B C D E F G H I);
-value_tree_tuple!(access_tupleA, B C D E F G H I J);
+value_tree_tuple!(access_tuple2 init_tuple2, B);
+value_tree_tuple!(access_tuple3 init_tuple3, B C);
+value_tree_tuple!(access_tuple4 init_tuple4, B C D);
+value_tree_tuple!(access_tuple5 init_tuple5, B C D E);
+value_tree_tuple!(access_tuple6 init_tuple6, B C D E F);
+value_tree_tuple!(access_tuple7 init_tuple7, B C D E F G);
+value_tree_tuple!(access_tuple8 init_tuple8, B C D E F G H);
+value_tree_tuple!(access_tuple9 init_tuple9, B C D E F G H I);
+value_tree_tuple!(access_tupleA init_tupleA, B C D E F G H I J);
 
 const WEIGHT_BASE: u32 = 0x80000000;
 
@@ -323,6 +410,8 @@ pub fn float_to_weight(f: f64) -> (u32, u32) {
 
 #[cfg(test)]
 mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     use super::*;
 
     #[test]
@@ -384,8 +473,8 @@ mod test {
     #[test]
     fn test_tuple_union() {
         let input = TupleUnion::new(
-            ((1, 10u32..20u32),
-             (1, 30u32..40u32)));
+            ((1, Arc::new(10u32..20u32)),
+             (1, Arc::new(30u32..40u32))));
         // Expect that 25% of cases pass (left input happens to be < 15, and
         // left is chosen as initial value). Of the 75% that fail, 50% should
         // converge to 15 and 50% to 30 (the latter because the left is beneath
@@ -421,9 +510,9 @@ mod test {
     #[test]
     fn test_tuple_union_weighting() {
         let input = TupleUnion::new((
-            (1, Just(0usize)),
-            (2, Just(1usize)),
-            (1, Just(2usize)),
+            (1, Arc::new(Just(0usize))),
+            (2, Arc::new(Just(1usize))),
+            (1, Arc::new(Just(2usize))),
         ));
 
         let mut counts = [0, 0, 0];
@@ -439,6 +528,106 @@ mod test {
         assert!(counts[1] > counts[2] * 3/2);
     }
 
+    #[test]
+    fn test_tuple_union_shrinks_through_all_options() {
+        let input = TupleUnion::new((
+            (1, Arc::new(Just(0usize))),
+            (1, Arc::new(Just(1usize))),
+            (1, Arc::new(Just(2usize))),
+        ));
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let mut case = input.new_value(&mut runner).unwrap();
+            let mut prev = case.current();
+            while case.simplify() {
+                assert!(case.current() < prev);
+                prev = case.current();
+            }
+            assert_eq!(0, case.current());
+        }
+    }
+
+    #[derive(Debug)]
+    struct CountingStrategy(Arc<AtomicUsize>, u32);
+
+    impl Strategy for CountingStrategy {
+        type Value = Just<u32>;
+
+        fn new_value(&self, _: &mut TestRunner)
+                     -> Result<Self::Value, String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Just(self.1))
+        }
+    }
+
+    #[test]
+    fn test_union_generates_fallbacks_lazily() {
+        let generated = Arc::new(AtomicUsize::new(0));
+        let input = Union::new(
+            (0..4).map(|v| CountingStrategy(generated.clone(), v)));
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            generated.store(0, Ordering::SeqCst);
+            let mut case = input.new_value(&mut runner).unwrap();
+            assert_eq!(1, generated.load(Ordering::SeqCst));
+
+            let initial = case.current();
+            let mut expected = initial;
+            while case.simplify() {
+                expected -= 1;
+                assert_eq!(expected, case.current());
+            }
+            assert_eq!(0, case.current());
+            assert_eq!(initial as usize + 1, generated.load(Ordering::SeqCst));
+        }
+    }
+
+    #[test]
+    fn test_tuple_union_generates_fallbacks_lazily() {
+        let generated = Arc::new(AtomicUsize::new(0));
+        let input = TupleUnion::new((
+            (1, Arc::new(CountingStrategy(generated.clone(), 0))),
+            (1, Arc::new(CountingStrategy(generated.clone(), 1))),
+            (1, Arc::new(CountingStrategy(generated.clone(), 2))),
+        ));
+
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            generated.store(0, Ordering::SeqCst);
+            let mut case = input.new_value(&mut runner).unwrap();
+            assert_eq!(1, generated.load(Ordering::SeqCst));
+
+            let initial = case.current();
+            while case.simplify() { }
+            assert_eq!(0, case.current());
+            assert_eq!(initial as usize + 1, generated.load(Ordering::SeqCst));
+        }
+    }
+
+    #[test]
+    fn test_union_complicate_returns_to_previous_pick() {
+        let input = Union::new(vec![Just(0u32), Just(1u32), Just(2u32)]);
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let mut case = input.new_value(&mut runner).unwrap();
+            let initial = case.current();
+            if 0 == initial {
+                assert!(!case.simplify());
+                continue;
+            }
+
+            assert!(case.simplify());
+            assert_eq!(initial - 1, case.current());
+            assert!(case.complicate());
+            assert_eq!(initial, case.current());
+            // The pick can no longer move below where it was.
+            assert!(!case.simplify());
+            assert_eq!(initial, case.current());
+        }
+    }
+
     #[test]
     fn test_tuple_union_all_sizes() {
         let mut runner = TestRunner::new(Config::default());
@@ -447,8 +636,8 @@ mod test {
         macro_rules! test {
             ($($part:expr),*) => {{
                 let input = TupleUnion::new((
-                    $((1, $part.clone())),*,
-                    (1, Just(0i32))
+                    $((1, Arc::new($part.clone()))),*,
+                    (1, Arc::new(Just(0i32)))
                 ));
 
                 let mut pass = false;
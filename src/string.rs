@@ -7,19 +7,21 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! Strategies for generating strings and byte strings from regular
-//! expressions.
+//! Strategies for generating strings and byte strings, either from regular
+//! expressions or from strategies for their characters.
 
 use std::borrow::Cow;
 use std::fmt;
+use std::ops::Range;
+use std::str;
 use std::u32;
 
+use rand::Rng;
 use regex_syntax as rs;
+use regex_syntax::hir::{Hir, HirKind, Look};
 
-use bool;
 use char;
 use collection;
-use bits;
 use num;
 use strategy::*;
 use test_runner::*;
@@ -30,10 +32,10 @@ quick_error! {
     #[derive(Debug)]
     pub enum Error {
         /// The string passed as the regex was not syntactically valid.
-        RegexSyntax(err: rs::Error) {
-            from()
-            cause(err)
-            description(err.description())
+        RegexSyntax(err: Box<rs::Error>) {
+            from(err: rs::Error) -> (Box::new(err))
+            cause(&**err)
+            description("invalid regex syntax")
             display("{}", err)
         }
         /// The regex was syntactically valid, but contains elements not
@@ -41,6 +43,12 @@ quick_error! {
         UnsupportedRegex(message: &'static str) {
             description(message)
         }
+        /// The regex can never match anything, for example because it
+        /// requires text before `^` or a word character on both sides of
+        /// `\\b`.
+        UnsatisfiableRegex(message: &'static str) {
+            description(message)
+        }
     }
 }
 
@@ -57,6 +65,69 @@ opaque_strategy_wrapper! {
         (Box<ValueTree<Value = T>>) -> T;
 }
 
+/// Options controlling how strings and byte strings are generated from
+/// regular expressions.
+///
+/// Use with functions such as `string_regex_with()`. The non-`_with`
+/// functions use `RegexOptions::default()`.
+#[derive(Clone, Debug)]
+pub struct RegexOptions {
+    /// The maximum number of times `*` and `+` repeat their operand, and the
+    /// maximum number of times `{n,}` repeats it beyond `n`.
+    ///
+    /// The default is 32.
+    pub max_repeat: u32,
+    /// How literals inside case-insensitive groups such as `(?i:foo)` are
+    /// generated. This has no effect on pre-parsed expressions.
+    ///
+    /// The default is `CaseInsensitivity::Vary`.
+    pub case_insensitivity: CaseInsensitivity,
+    /// Which characters (or bytes, in a `(?-u)` group) `.` generates. In the
+    /// absence of the `s` flag, newlines are excluded regardless. This also
+    /// applies to other classes of all characters, such as `[\s\S]`.
+    ///
+    /// The default is `Dot::Any`.
+    pub dot: Dot,
+    // Needs to be public so FRU syntax can be used.
+    #[doc(hidden)]
+    pub _non_exhaustive: (),
+}
+
+impl Default for RegexOptions {
+    fn default() -> RegexOptions {
+        RegexOptions {
+            max_repeat: 32,
+            case_insensitivity: CaseInsensitivity::Vary,
+            dot: Dot::Any,
+            _non_exhaustive: (),
+        }
+    }
+}
+
+/// How literals inside case-insensitive groups are generated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaseInsensitivity {
+    /// The case of each letter is chosen independently.
+    Vary,
+    /// Letters are always generated in the case in which they are written.
+    AsWritten,
+}
+
+/// Which characters `.` generates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Dot {
+    /// Any character (or any byte in a `(?-u)` group).
+    Any,
+    /// Only ASCII characters, including control characters.
+    Ascii,
+    /// Only printable ASCII characters, i.e., space through `~`.
+    AsciiPrintable,
+    /// Only characters within the given inclusive ranges. In a `(?-u)` group,
+    /// only the parts of the ranges below `'\u{100}'` are used, each
+    /// character standing for the byte of the same value.
+    Ranges(Vec<(char, char)>),
+}
+
 impl Strategy for str {
     type Value = RegexGeneratorValueTree<String>;
 
@@ -69,187 +140,1068 @@ impl Strategy for str {
 /// Creates a strategy which generates strings matching the given regular
 /// expression.
 ///
+/// Any pattern accepted by the `regex` crate is supported. Anchors such as
+/// `^` and `\z` and word boundaries are supported too; values are only
+/// generated where they hold. If they can never all hold, for example in
+/// `a^b`, `Error::UnsatisfiableRegex` is returned.
+///
 /// If you don't need error handling and aren't limited by setup time, it is
 /// also possible to directly use a `&str` as a strategy with the same effect.
 pub fn string_regex(regex: &str)
                     -> Result<RegexGeneratorStrategy<String>, Error> {
-    string_regex_parsed(&rs::Expr::parse(regex)?)
+    string_regex_with(regex, &RegexOptions::default())
 }
 
 /// Like `string_regex()`, but allows providing a pre-parsed expression.
-pub fn string_regex_parsed(expr: &rs::Expr)
+///
+/// The expression must only match valid UTF-8.
+pub fn string_regex_parsed(expr: &Hir)
                            -> Result<RegexGeneratorStrategy<String>, Error> {
-    bytes_regex_parsed(expr).map(
+    string_regex_parsed_with(expr, &RegexOptions::default())
+}
+
+/// Like `string_regex()`, but uses the given options instead of the defaults.
+pub fn string_regex_with(regex: &str, options: &RegexOptions)
+                         -> Result<RegexGeneratorStrategy<String>, Error> {
+    string_regex_parsed_with(&parse(regex, options, true)?, options)
+}
+
+/// Like `string_regex_parsed()`, but uses the given options instead of the
+/// defaults.
+///
+/// `options.case_insensitivity` has no effect, since case-insensitivity has
+/// already been resolved into character classes in a parsed expression.
+pub fn string_regex_parsed_with(expr: &Hir, options: &RegexOptions)
+                                -> Result<RegexGeneratorStrategy<String>,
+                                          Error> {
+    if !expr.properties().is_utf8() {
+        return Err(Error::UnsupportedRegex(
+            "regex for string generation can match invalid UTF-8"));
+    }
+
+    bytes_regex_parsed_with(expr, options).map(
         |v| v.prop_map(|bytes| String::from_utf8(bytes).expect(
             "non-utf8 string")).boxed()).map(RegexGeneratorStrategy)
 }
 
 /// Creates a strategy which generates byte strings matching the given regular
 /// expression.
+///
+/// Unlike `string_regex()`, this allows parts of the regex with Unicode
+/// disabled to match arbitrary bytes, e.g., `(?-u:[\x80-\xFF])`.
 pub fn bytes_regex(regex: &str)
                    -> Result<RegexGeneratorStrategy<Vec<u8>>, Error> {
-    bytes_regex_parsed(&rs::Expr::parse(regex)?)
+    bytes_regex_with(regex, &RegexOptions::default())
 }
 
 /// Like `bytes_regex()`, but allows providing a pre-parsed expression.
-pub fn bytes_regex_parsed(expr: &rs::Expr)
+pub fn bytes_regex_parsed(expr: &Hir)
                           -> Result<RegexGeneratorStrategy<Vec<u8>>, Error> {
-    use self::rs::Expr::*;
-
-    match *expr {
-        Empty => Ok(Just(vec![]).boxed()),
-        Literal { ref chars, casei: false } =>
-            Ok(Just(chars.iter().map(|&c| c).collect::<String>()
-                         .into_bytes()).boxed()),
-        Literal { ref chars, casei: true } => {
-            let chars = chars.to_owned();
-            Ok(bits::bitset::between(0, chars.len())
-               .prop_map(move |cases|
-                         cases.into_bit_vec().iter().zip(chars.iter())
-                         .map(|(case, &ch)| flip_case_to_bytes(case, ch))
-                         .fold(vec![], |mut accum, rhs| {
-                             accum.extend(rhs);
-                             accum
-                         }))
-               .boxed())
-        },
-        LiteralBytes { ref bytes, casei: false } =>
-            Ok(Just(bytes.to_owned()).boxed()),
-        LiteralBytes { ref bytes, casei: true } => {
-            let bytes = bytes.to_owned();
-            Ok(bits::bitset::between(0, bytes.len())
-               .prop_map(move |cases|
-                         cases.into_bit_vec().iter().zip(bytes.iter())
-                         .map(|(case, &byte)| flip_ascii_case(case, byte))
-                         .collect::<Vec<_>>()).boxed())
+    bytes_regex_parsed_with(expr, &RegexOptions::default())
+}
+
+/// Like `bytes_regex()`, but uses the given options instead of the defaults.
+pub fn bytes_regex_with(regex: &str, options: &RegexOptions)
+                        -> Result<RegexGeneratorStrategy<Vec<u8>>, Error> {
+    bytes_regex_parsed_with(&parse(regex, options, false)?, options)
+}
+
+/// Like `bytes_regex_parsed()`, but uses the given options instead of the
+/// defaults.
+///
+/// `options.case_insensitivity` has no effect, since case-insensitivity has
+/// already been resolved into character classes in a parsed expression.
+pub fn bytes_regex_parsed_with(expr: &Hir, options: &RegexOptions)
+                               -> Result<RegexGeneratorStrategy<Vec<u8>>,
+                                         Error> {
+    let context = Context::new(expr, options);
+    let strategy = compile(expr, &context, States::initial())?;
+    if !reach(expr, &context, States::initial()).can_end() {
+        return Err(Error::UnsatisfiableRegex(UNSATISFIABLE));
+    }
+
+    // The analysis of where assertions can hold is conservative, so check
+    // the generated values as well.
+    Ok(RegexGeneratorStrategy(
+        strategy.prop_filter("regex assertions not satisfied".to_owned(),
+                             |generated| generated.assertions_hold())
+            .prop_map(|generated| generated.bytes).boxed()))
+}
+
+/// Creates a strategy which generates strings which do not match the given
+/// regular expression in full, for testing that invalid inputs are rejected.
+///
+/// Values are "near misses": strings matching the regex as generated by
+/// `string_regex()`, with between one and three characters deleted, inserted
+/// or replaced. Since an edit may happen to produce another match, every
+/// value is checked against the regex using the `regex` crate. As a result,
+/// generating values fails with too many rejections if nearly every string
+/// matches the regex, as with `(?s).*`.
+pub fn not_matching(regex: &str)
+                    -> Result<RegexGeneratorStrategy<String>, Error> {
+    let matches = string_regex(regex)?;
+    // Anchor the parsed expression rather than the source, which may end in a
+    // `(?x)` comment that would swallow anything appended to it.
+    let anchored = Hir::concat(vec![
+        Hir::look(Look::Start), rs::Parser::new().parse(regex)?,
+        Hir::look(Look::End),
+    ]);
+    let matcher = ::regex::Regex::new(&anchored.to_string())
+        .map_err(|_| Error::UnsupportedRegex(
+            "regex cannot be compiled for matching"))?;
+
+    let edit = (0..3u8, num::u32::ANY, Union::new(vec![
+        char::range(' ', '~').boxed(),
+        char::ANY.boxed(),
+    ])).prop_map(|(kind, position, ch)| match kind {
+        0 => Edit::Delete(position),
+        1 => Edit::Insert(position, ch),
+        _ => Edit::Replace(position, ch),
+    });
+
+    Ok(RegexGeneratorStrategy(
+        (matches, collection::vec(edit, 1..4))
+            .prop_map(|(s, edits)| apply_edits(&s, &edits))
+            .prop_filter("edited string still matches regex".to_owned(),
+                         move |s| !matcher.is_match(s))
+            .boxed()))
+}
+
+/// An edit made to a string by `not_matching()`. Positions are taken modulo
+/// the number of possible positions.
+#[derive(Clone, Copy, Debug)]
+enum Edit {
+    Delete(u32),
+    Insert(u32, char),
+    Replace(u32, char),
+}
+
+fn apply_edits(s: &str, edits: &[Edit]) -> String {
+    let mut chars = s.chars().collect::<Vec<_>>();
+    for &edit in edits {
+        let len = chars.len() as u32;
+        match edit {
+            Edit::Insert(position, ch) =>
+                chars.insert((position % (len + 1)) as usize, ch),
+            Edit::Delete(position) if len > 0 => {
+                chars.remove((position % len) as usize);
+            },
+            Edit::Replace(position, ch) if len > 0 =>
+                chars[(position % len) as usize] = ch,
+            // Nothing to delete or replace
+            _ => (),
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// Strategy which generates strings of characters drawn from another strategy,
+/// with a length in a certain range.
+///
+/// Created by the `string_of()` and `string_of_utf8_len()` functions in the
+/// same module.
+#[derive(Clone, Debug)]
+pub struct StringOfStrategy<T : Strategy> {
+    element: T,
+    size: Range<usize>,
+    in_bytes: bool,
+}
+
+/// Creates a strategy to generate `String`s of characters drawn from
+/// `element`, with a length in characters within `size`.
+///
+/// Unlike collecting `collection::vec(element, size)` into a `String`, values
+/// shrink by removing characters and then simplifying the characters that
+/// remain, and are shown as strings when a test fails.
+///
+/// Generating values fails if `size` is empty.
+pub fn string_of<T : Strategy>(element: T, size: Range<usize>)
+                               -> StringOfStrategy<T>
+where T::Value : ValueTree<Value = char> {
+    StringOfStrategy { element, size, in_bytes: false }
+}
+
+/// Like `string_of()`, but with the length measured in bytes of UTF-8, i.e.,
+/// `String::len()`, rather than in characters.
+///
+/// Generating values fails if `size` is empty. If `element` cannot generate
+/// characters short enough to fit within `size`, generating values fails with
+/// too many local rejections.
+pub fn string_of_utf8_len<T : Strategy>(element: T, size: Range<usize>)
+                                        -> StringOfStrategy<T>
+where T::Value : ValueTree<Value = char> {
+    StringOfStrategy { element, size, in_bytes: true }
+}
+
+impl<T : Strategy> Strategy for StringOfStrategy<T>
+where T::Value : ValueTree<Value = char> {
+    type Value = StringOfValueTree<T::Value>;
+
+    fn new_value(&self, runner: &mut TestRunner)
+                 -> Result<Self::Value, String> {
+        if self.size.start >= self.size.end {
+            return Err(format!("Empty string length range {:?}", self.size));
+        }
+
+        let target = runner.rng().gen_range(self.size.start, self.size.end);
+        let mut elements = Vec::new();
+        let mut len = 0;
+        while len < target {
+            let element = self.element.new_value(runner)?;
+            let element_len = if self.in_bytes {
+                element.current().len_utf8()
+            } else {
+                1
+            };
+
+            if len + element_len < self.size.end {
+                len += element_len;
+                elements.push(element);
+            } else {
+                runner.reject_local(
+                    "character too long for remaining length".to_owned())?;
+            }
+        }
+
+        Ok(StringOfValueTree {
+            included: vec![true; elements.len()],
+            elements,
+            len,
+            min_len: self.size.start,
+            in_bytes: self.in_bytes,
+            shrink: StringShrink::Delete(0),
+            prev_shrink: None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum StringShrink {
+    Delete(usize),
+    Simplify(usize),
+}
+
+/// `ValueTree` corresponding to `StringOfStrategy`.
+#[derive(Clone, Debug)]
+pub struct StringOfValueTree<T : ValueTree<Value = char>> {
+    elements: Vec<T>,
+    included: Vec<bool>,
+    /// The length of the current value, in the units of `min_len`.
+    len: usize,
+    min_len: usize,
+    in_bytes: bool,
+    shrink: StringShrink,
+    prev_shrink: Option<StringShrink>,
+}
+
+impl<T : ValueTree<Value = char>> StringOfValueTree<T> {
+    fn element_len(&self, ix: usize) -> usize {
+        if self.in_bytes {
+            self.elements[ix].current().len_utf8()
+        } else {
+            1
+        }
+    }
+
+    /// Complicates element `ix`, which was just simplified, until the value
+    /// is long enough again. Returns whether it was.
+    fn restore_len(&mut self, ix: usize, old_len: usize) -> bool {
+        loop {
+            let new_len = self.len - old_len + self.element_len(ix);
+            if new_len >= self.min_len {
+                self.len = new_len;
+                return true;
+            }
+            if !self.elements[ix].complicate() {
+                return false;
+            }
+        }
+    }
+}
+
+impl<T : ValueTree<Value = char>> ValueTree for StringOfValueTree<T> {
+    type Value = String;
+
+    fn current(&self) -> String {
+        self.elements.iter().zip(&self.included)
+            .filter(|&(_, &included)| included)
+            .map(|(element, _)| element.current())
+            .collect()
+    }
+
+    fn simplify(&mut self) -> bool {
+        // As with `Vec`s, first delete characters for as long as possible,
+        // then simplify each remaining one in turn.
+        while let StringShrink::Delete(ix) = self.shrink {
+            if ix >= self.elements.len() {
+                self.shrink = StringShrink::Simplify(0);
+            } else if self.len - self.element_len(ix) < self.min_len {
+                self.shrink = StringShrink::Delete(ix + 1);
+            } else {
+                self.len -= self.element_len(ix);
+                self.included[ix] = false;
+                self.prev_shrink = Some(self.shrink);
+                self.shrink = StringShrink::Delete(ix + 1);
+                return true;
+            }
+        }
+
+        while let StringShrink::Simplify(ix) = self.shrink {
+            if ix >= self.elements.len() {
+                return false;
+            }
+
+            let old_len = self.element_len(ix);
+            if self.included[ix] && self.elements[ix].simplify() {
+                // Simplifying a character can make its encoding shorter;
+                // back off if that makes the whole string too short.
+                if self.restore_len(ix, old_len) {
+                    self.prev_shrink = Some(self.shrink);
+                    return true;
+                }
+            }
+            self.shrink = StringShrink::Simplify(ix + 1);
+        }
+
+        panic!("Unexpected shrink state");
+    }
+
+    fn complicate(&mut self) -> bool {
+        match self.prev_shrink {
+            None => false,
+            Some(StringShrink::Delete(ix)) => {
+                self.included[ix] = true;
+                self.len += self.element_len(ix);
+                self.prev_shrink = None;
+                true
+            },
+            Some(StringShrink::Simplify(ix)) => {
+                let old_len = self.element_len(ix);
+                if self.elements[ix].complicate() {
+                    self.len = self.len - old_len + self.element_len(ix);
+                    true
+                } else {
+                    self.prev_shrink = None;
+                    false
+                }
+            },
+        }
+    }
+}
+
+/// Parses `regex`, applying `options.case_insensitivity`. If `utf8` is true,
+/// the result only matches valid UTF-8.
+fn parse(regex: &str, options: &RegexOptions, utf8: bool)
+         -> Result<Hir, Error> {
+    let mut ast = rs::ast::parse::Parser::new().parse(regex)
+        .map_err(rs::Error::from)?;
+    if CaseInsensitivity::AsWritten == options.case_insensitivity {
+        remove_case_insensitivity(&mut ast);
+    }
+
+    Ok(rs::hir::translate::TranslatorBuilder::new().utf8(utf8).build()
+       .translate(regex, &ast).map_err(rs::Error::from)?)
+}
+
+/// Removes every `i` flag from `ast`.
+fn remove_case_insensitivity(ast: &mut rs::ast::Ast) {
+    use self::rs::ast::*;
+
+    fn remove_from(flags: &mut Flags) {
+        flags.items.retain(|item| FlagsItemKind::Flag(Flag::CaseInsensitive)
+                           != item.kind);
+    }
+
+    match *ast {
+        Ast::Flags(ref mut set) => remove_from(&mut set.flags),
+        Ast::Group(ref mut group) => {
+            if let GroupKind::NonCapturing(ref mut flags) = group.kind {
+                remove_from(flags);
+            }
+            remove_case_insensitivity(&mut group.ast);
         },
+        Ast::Repetition(ref mut repetition) =>
+            remove_case_insensitivity(&mut repetition.ast),
+        Ast::Alternation(ref mut alternation) =>
+            alternation.asts.iter_mut().for_each(remove_case_insensitivity),
+        Ast::Concat(ref mut concat) =>
+            concat.asts.iter_mut().for_each(remove_case_insensitivity),
+        _ => (),
+    }
+}
+
+const UNSATISFIABLE: &str =
+    "regex can never match due to its anchors, word boundaries or empty \
+     classes";
+
+/// Information needed throughout compiling a regex.
+struct Context<'a> {
+    options: &'a RegexOptions,
+    /// Whether the regex contains any assertions or empty classes. If not,
+    /// there is no need to analyse where it can match.
+    analyse: bool,
+    /// The ranges of characters matched by `\w`, if `analyse` is true.
+    word: Vec<(char, char)>,
+}
+
+impl<'a> Context<'a> {
+    fn new(expr: &Hir, options: &'a RegexOptions) -> Self {
+        let analyse = needs_analysis(expr);
+        let word = if !analyse {
+            vec![]
+        } else if let HirKind::Class(rs::hir::Class::Unicode(ref class)) =
+            *rs::parse(r"\w").expect("failed to parse \\w").kind()
+        {
+            unicode_ranges(class)
+        } else {
+            unreachable!()
+        };
+        Context { options, analyse, word }
+    }
+}
+
+fn needs_analysis(expr: &Hir) -> bool {
+    match *expr.kind() {
+        HirKind::Look(_) => true,
+        HirKind::Class(rs::hir::Class::Unicode(ref class)) =>
+            class.ranges().is_empty(),
+        HirKind::Class(rs::hir::Class::Bytes(ref class)) =>
+            class.ranges().is_empty(),
+        ref kind => kind.subs().iter().any(needs_analysis),
+    }
+}
+
+/// Bytes generated for part of a regex, along with the assertions which must
+/// hold at given offsets within them.
+#[derive(Clone, Debug, Default)]
+struct Generated {
+    bytes: Vec<u8>,
+    assertions: Vec<(usize, Look)>,
+}
+
+impl Generated {
+    fn new(bytes: Vec<u8>) -> Self {
+        Generated { bytes, assertions: vec![] }
+    }
+
+    fn extend(&mut self, other: Generated) {
+        let offset = self.bytes.len();
+        self.bytes.extend(other.bytes);
+        self.assertions.extend(other.assertions.into_iter().map(
+            |(at, look)| (at + offset, look)));
+    }
+
+    fn assertions_hold(&self) -> bool {
+        self.assertions.iter().all(
+            |&(at, look)| look_holds(look, &self.bytes, at))
+    }
+}
+
+fn concat(parts: Vec<Generated>) -> Generated {
+    parts.into_iter().fold(Generated::default(), |mut accum, part| {
+        accum.extend(part);
+        accum
+    })
+}
 
-        AnyChar => Ok(char::ANY.boxed().prop_map(|c| to_bytes(c)).boxed()),
-        AnyCharNoNL => {
-            static NONL_RANGES: &[(char,char)] = &[
-                ('\x00', '\x09'),
-                // Multiple instances of the latter range to partially make up
-                // for the bias of having such a tiny range in the control
-                // characters.
-                ('\x0B', ::std::char::MAX),
-                ('\x0B', ::std::char::MAX),
-                ('\x0B', ::std::char::MAX),
-                ('\x0B', ::std::char::MAX),
-                ('\x0B', ::std::char::MAX),
-            ];
-            Ok(char::ranges(Cow::Borrowed(NONL_RANGES))
-               .prop_map(|c| to_bytes(c)).boxed())
+fn compile(expr: &Hir, cx: &Context, states: States)
+           -> Result<BoxedStrategy<Generated>, Error> {
+    use self::rs::hir::HirKind::*;
+
+    let options = cx.options;
+    Ok(match *expr.kind() {
+        Empty => Just(Generated::default()).boxed(),
+        Literal(rs::hir::Literal(ref bytes)) =>
+            Just(Generated::new(bytes.to_vec())).boxed(),
+
+        Class(rs::hir::Class::Unicode(ref class)) => {
+            let (ranges, any) = class_chars(class, options);
+            if ranges.is_empty() {
+                return Err(if dot_class(class).is_some() {
+                    Error::UnsupportedRegex(
+                        "no characters available for `.` with the given \
+                         options")
+                } else {
+                    Error::UnsatisfiableRegex(UNSATISFIABLE)
+                });
+            }
+            compile_chars(cx, states, ranges, any)
         },
-        AnyByte => Ok(num::u8::ANY.prop_map(|b| vec![b]).boxed()),
-        AnyByteNoNL => Ok((0xBu8..).boxed()
-                          .prop_union((..0xAu8).boxed())
-                          .prop_map(|b| vec![b]).boxed()),
-
-        Class(ref class) => {
-            let ranges = (**class).iter().map(
-                |&rs::ClassRange { start, end }| (start, end)).collect();
-            Ok(char::ranges(Cow::Owned(ranges))
-               .prop_map(to_bytes).boxed())
-        }
-
-        ClassBytes(ref class) => {
-            let subs = (**class).iter().map(
-                |&rs::ByteRange { start, end }| if 255u8 == end {
-                    (start..).boxed()
+        Class(rs::hir::Class::Bytes(ref class)) => {
+            let ranges = class_bytes(class, options);
+            if ranges.is_empty() {
+                return Err(if dot_byte_class(class).is_some() {
+                    Error::UnsupportedRegex(
+                        "no bytes available for `.` with the given options")
                 } else {
-                    (start..end).boxed()
-                }).collect::<Vec<_>>();
-            Ok(Union::new(subs)
-               .prop_map(|b| vec![b]).boxed())
+                    Error::UnsatisfiableRegex(UNSATISFIABLE)
+                });
+            }
+            compile_bytes(states, ranges)
         },
 
-        Group { ref e, .. } => bytes_regex_parsed(e).map(|v| v.0),
-
-        Repeat { ref e, r, .. } => {
-            let range = match r {
-                rs::Repeater::ZeroOrOne => 0..2,
-                rs::Repeater::ZeroOrMore => 0..33,
-                rs::Repeater::OneOrMore => 1..33,
-                rs::Repeater::Range { min, max } => {
-                    let max = if let Some(max) = max {
-                        if u32::MAX == max {
-                            return Err(Error::UnsupportedRegex(
-                                "Cannot have repetition max of u32::MAX"));
-                        } else {
-                            max as usize + 1
-                        }
-                    } else if min < u32::MAX as u32 / 2 {
-                        min as usize * 2
-                    } else {
-                        u32::MAX as usize
-                    };
-
-                    (min as usize)..max
-                },
-            };
-            Ok(collection::vec(bytes_regex_parsed(e)?, range)
-               .prop_map(|parts| parts.into_iter().fold(
-                   vec![], |mut accum, child| { accum.extend(child); accum }))
-               .boxed())
+        Look(look) => Just(Generated {
+            bytes: vec![],
+            assertions: vec![(0, look)],
+        }).boxed(),
+
+        Capture(ref capture) => compile(&capture.sub, cx, states)?,
+
+        Repetition(ref repetition) => {
+            let range = repeat_range(repetition, options)?;
+            let (starts, _) = reach_repeat(&repetition.sub, cx, states, &range);
+            if reach(&repetition.sub, cx, starts).is_empty() {
+                // The operand can never match here, so only zero repetitions
+                // are possible. If that is not allowed either, the analysis
+                // at the top level will reject the regex.
+                Just(Generated::default()).boxed()
+            } else {
+                collection::vec(compile(&repetition.sub, cx, starts)?, range)
+                    .prop_map(concat).boxed()
+            }
         },
 
         Concat(ref subs) => {
-            let subs = subs.iter().map(|e| bytes_regex_parsed(e))
-                .collect::<Result<Vec<_>, _>>()?;
-            Ok(subs.into_iter()
-               .fold(None, |accum, rhs| match accum {
-                   None => Some(rhs.boxed()),
-                   Some(accum) => Some(
-                       (accum, rhs).prop_map(|(mut lhs, rhs)| {
-                           lhs.extend(rhs);
-                           lhs
-                       }).boxed()),
-               }).unwrap_or_else(
-                   || Just(vec![]).boxed()))
+            let mut states = states;
+            let mut parts = Vec::with_capacity(subs.len());
+            for sub in subs {
+                parts.push(compile(sub, cx, states)?);
+                states = reach(sub, cx, states);
+            }
+            parts.into_iter()
+                .fold(None, |accum, rhs| match accum {
+                    None => Some(rhs),
+                    Some(accum) => Some(
+                        (accum, rhs).prop_map(|(mut lhs, rhs)| {
+                            lhs.extend(rhs);
+                            lhs
+                        }).boxed()),
+                }).unwrap_or_else(
+                    || Just(Generated::default()).boxed())
         },
 
-        Alternate(ref subs) => {
-            let subs = subs.iter().map(|e| bytes_regex_parsed(e))
+        Alternation(ref subs) => {
+            // Leave out alternatives which can never match here so that we
+            // don't waste time generating values which will be rejected.
+            let subs = subs.iter()
+                .filter(|sub| !reach(sub, cx, states).is_empty())
+                .map(|sub| compile(sub, cx, states))
                 .collect::<Result<Vec<_>, _>>()?;
-            Ok(Union::new(subs).boxed())
+            if subs.is_empty() {
+                return Err(Error::UnsatisfiableRegex(UNSATISFIABLE));
+            }
+            Union::new(subs).boxed()
         },
+    })
+}
+
+/// Generates the UTF-8 encoding of a character within `ranges`, or any
+/// character if `any` is true.
+fn compile_chars(cx: &Context, states: States,
+                 ranges: Cow<'static, [(char, char)]>, any: bool)
+                 -> BoxedStrategy<Generated> {
+    let kinds = range_kinds(&ranges, &cx.word);
+    let chars = if any {
+        char::ANY.boxed()
+    } else {
+        char::ranges(ranges).boxed()
+    };
+
+    // If a preceding assertion constrains this character, pick only
+    // characters which satisfy it.
+    let allowed = states.next_kinds();
+    let chars = if cx.analyse && 0 != kinds & !allowed {
+        chars.prop_filter("character excluded by regex assertion".to_owned(),
+                          move |&c| 0 != char_kind(c) & allowed).boxed()
+    } else {
+        chars
+    };
+    chars.prop_map(|c| Generated::new(to_bytes(c))).boxed()
+}
+
+/// Generates a single byte within `ranges`.
+fn compile_bytes(states: States, ranges: Vec<(u8, u8)>)
+                 -> BoxedStrategy<Generated> {
+    let kinds = byte_range_kinds(&ranges);
+    let bytes = Union::new(ranges.into_iter().map(
+        |(start, end)| (start..=end).boxed())).boxed();
 
-        StartLine |
-        EndLine |
-        StartText |
-        EndText => Err(Error::UnsupportedRegex(
-            "line/text anchors not supported for string generation")),
-
-        WordBoundary |
-        NotWordBoundary |
-        WordBoundaryAscii |
-        NotWordBoundaryAscii => Err(Error::UnsupportedRegex(
-            "word boundary tests not supported for string generation")),
-    }.map(RegexGeneratorStrategy)
-}
-
-fn flip_case_to_bytes(flip: bool, ch: char) -> Vec<u8> {
-    if flip && ch.is_uppercase() {
-        ch.to_lowercase().collect::<String>().into_bytes()
-    } else if flip && ch.is_lowercase() {
-        ch.to_uppercase().collect::<String>().into_bytes()
+    let allowed = states.next_kinds();
+    let bytes = if 0 != kinds & !allowed {
+        bytes.prop_filter("byte excluded by regex assertion".to_owned(),
+                          move |&b| 0 != byte_kind(b) & allowed).boxed()
     } else {
-        to_bytes(ch)
+        bytes
+    };
+    bytes.prop_map(|b| Generated::new(vec![b])).boxed()
+}
+
+fn unicode_ranges(class: &rs::hir::ClassUnicode) -> Vec<(char, char)> {
+    class.iter().map(|r| (r.start(), r.end())).collect()
+}
+
+fn byte_ranges(class: &rs::hir::ClassBytes) -> Vec<(u8, u8)> {
+    class.iter().map(|r| (r.start(), r.end())).collect()
+}
+
+/// If `class` is what `.` compiles to, i.e., it contains every character
+/// except possibly `\n` and `\r`, returns whether it contains each of them.
+fn dot_class(class: &rs::hir::ClassUnicode) -> Option<(bool, bool)> {
+    let mut excluded = class.clone();
+    excluded.negate();
+    dot_exclusions(excluded.iter().map(|r| (r.start() as u32, r.end() as u32)))
+}
+
+/// Like `dot_class()`, but for classes of bytes.
+fn dot_byte_class(class: &rs::hir::ClassBytes) -> Option<(bool, bool)> {
+    let mut excluded = class.clone();
+    excluded.negate();
+    dot_exclusions(excluded.iter().map(|r| (r.start() as u32, r.end() as u32)))
+}
+
+fn dot_exclusions<I : Iterator<Item = (u32, u32)>>(excluded: I)
+                                                   -> Option<(bool, bool)> {
+    let (mut newline, mut cr) = (true, true);
+    for range in excluded {
+        match range {
+            (0x0A, 0x0A) => newline = false,
+            (0x0D, 0x0D) => cr = false,
+            _ => return None,
+        }
     }
+    Some((newline, cr))
 }
 
-fn to_bytes(ch: char) -> Vec<u8> {
-    [ch].iter().map(|&c|c).collect::<String>().into_bytes()
+/// Returns the characters to generate for `class`, and whether they are all
+/// characters.
+fn class_chars(class: &rs::hir::ClassUnicode, options: &RegexOptions)
+               -> (Cow<'static, [(char, char)]>, bool) {
+    match dot_class(class) {
+        Some((newline, cr)) => (dot_ranges(&options.dot, newline, cr),
+                                Dot::Any == options.dot && newline && cr),
+        None => (Cow::Owned(unicode_ranges(class)), false),
+    }
+}
+
+/// Returns the bytes to generate for `class`.
+fn class_bytes(class: &rs::hir::ClassBytes, options: &RegexOptions)
+               -> Vec<(u8, u8)> {
+    match dot_byte_class(class) {
+        Some((newline, cr)) => dot_byte_ranges(&options.dot, newline, cr),
+        None => byte_ranges(class),
+    }
+}
+
+/// Returns the range of the number of times to repeat an expression.
+fn repeat_range(repetition: &rs::hir::Repetition, options: &RegexOptions)
+                -> Result<Range<usize>, Error> {
+    Ok(match (repetition.min, repetition.max) {
+        (_, Some(u32::MAX)) => return Err(Error::UnsupportedRegex(
+            "Cannot have repetition max of u32::MAX")),
+        (min, Some(max)) => (min as usize)..(max as usize + 1),
+        (0, None) => 0..options.max_repeat as usize + 1,
+        (1, None) => 1..options.max_repeat.max(1) as usize + 1,
+        (min, None) => (min as usize)..(min as usize).saturating_add(
+            options.max_repeat as usize).saturating_add(1),
+    })
 }
 
-fn flip_ascii_case(flip: bool, ch: u8) -> u8 {
-    if flip && ch >= b'a' && ch <= b'z' {
-        ch - b'a' + b'A'
-    } else if flip && ch >= b'A' && ch <= b'Z' {
-        ch - b'A' + b'a'
+/// Returns the characters `.` can generate under the given options.
+fn dot_ranges(dot: &Dot, newline: bool, cr: bool)
+              -> Cow<'static, [(char, char)]> {
+    static ANY: &[(char,char)] = &[('\x00', ::std::char::MAX)];
+    static ANY_NONL: &[(char,char)] = &[
+        ('\x00', '\x09'),
+        // Multiple instances of the latter range to partially make up for the
+        // bias of having such a tiny range in the control characters.
+        ('\x0B', ::std::char::MAX),
+        ('\x0B', ::std::char::MAX),
+        ('\x0B', ::std::char::MAX),
+        ('\x0B', ::std::char::MAX),
+        ('\x0B', ::std::char::MAX),
+    ];
+    static ASCII: &[(char,char)] = &[('\x00', '\x7F')];
+    static ASCII_NONL: &[(char,char)] = &[('\x00', '\x09'), ('\x0B', '\x7F')];
+    static ASCII_PRINTABLE: &[(char,char)] = &[(' ', '~')];
+
+    let ranges = match (dot, newline) {
+        (Dot::Any, true) => Cow::Borrowed(ANY),
+        (Dot::Any, false) => Cow::Borrowed(ANY_NONL),
+        (Dot::Ascii, true) => Cow::Borrowed(ASCII),
+        (Dot::Ascii, false) => Cow::Borrowed(ASCII_NONL),
+        (Dot::AsciiPrintable, _) => Cow::Borrowed(ASCII_PRINTABLE),
+        (Dot::Ranges(ranges), true) => Cow::Owned(ranges.clone()),
+        (Dot::Ranges(ranges), false) => Cow::Owned(exclude(ranges, '\n')),
+    };
+    if cr {
+        ranges
     } else {
-        ch
+        Cow::Owned(exclude(&ranges, '\r'))
+    }
+}
+
+/// Returns the bytes `.` can generate in a `(?-u)` group under the given
+/// options.
+fn dot_byte_ranges(dot: &Dot, newline: bool, cr: bool) -> Vec<(u8, u8)> {
+    let mut ranges = match *dot {
+        Dot::Any => vec![('\x00', '\u{FF}')],
+        Dot::Ascii => vec![('\x00', '\x7F')],
+        Dot::AsciiPrintable => vec![(' ', '~')],
+        Dot::Ranges(ref ranges) => ranges.iter()
+            .filter(|&&(start, _)| start <= '\u{FF}')
+            .map(|&(start, end)| (start, end.min('\u{FF}')))
+            .collect(),
+    };
+    if !newline {
+        ranges = exclude(&ranges, '\n');
+    }
+    if !cr {
+        ranges = exclude(&ranges, '\r');
     }
+
+    ranges.into_iter()
+        .filter(|&(start, end)| start <= end)
+        .map(|(start, end)| (start as u8, end as u8))
+        .collect()
+}
+
+/// Splits the given inclusive ranges so that none contains the ASCII control
+/// character `excluded`.
+fn exclude(ranges: &[(char, char)], excluded: char) -> Vec<(char, char)> {
+    let before = (excluded as u8 - 1) as char;
+    let after = (excluded as u8 + 1) as char;
+    ranges.iter().flat_map(|&(start, end)| {
+        if start <= excluded && excluded <= end {
+            vec![(start, before), (after, end)].into_iter()
+                .filter(|&(s, e)| s <= e)
+                .collect()
+        } else {
+            vec![(start, end)]
+        }
+    }).collect()
+}
+
+//==============================================================================
+// Assertions
+//==============================================================================
+
+/// Returns whether `look` holds at offset `at` of `bytes`.
+fn look_holds(look: Look, bytes: &[u8], at: usize) -> bool {
+    let byte_before = at.checked_sub(1).map(|at| bytes[at]);
+    let byte_after = bytes.get(at).cloned();
+    let ascii_before = byte_before.is_some_and(rs::is_word_byte);
+    let ascii_after = byte_after.is_some_and(rs::is_word_byte);
+    let word_before = char_before(bytes, at)
+        .is_some_and(rs::is_word_character);
+    let word_after = char_after(bytes, at)
+        .is_some_and(rs::is_word_character);
+
+    match look {
+        Look::Start => 0 == at,
+        Look::End => bytes.len() == at,
+        Look::StartLF => 0 == at || Some(b'\n') == byte_before,
+        Look::EndLF => bytes.len() == at || Some(b'\n') == byte_after,
+        Look::StartCRLF => 0 == at || Some(b'\n') == byte_before ||
+            (Some(b'\r') == byte_before && Some(b'\n') != byte_after),
+        Look::EndCRLF => bytes.len() == at || Some(b'\r') == byte_after ||
+            (Some(b'\n') == byte_after && Some(b'\r') != byte_before),
+        Look::WordAscii => ascii_before != ascii_after,
+        Look::WordAsciiNegate => ascii_before == ascii_after,
+        Look::WordUnicode => word_before != word_after,
+        Look::WordUnicodeNegate => word_before == word_after,
+        Look::WordStartAscii => !ascii_before && ascii_after,
+        Look::WordEndAscii => ascii_before && !ascii_after,
+        Look::WordStartUnicode => !word_before && word_after,
+        Look::WordEndUnicode => word_before && !word_after,
+        Look::WordStartHalfAscii => !ascii_before,
+        Look::WordEndHalfAscii => !ascii_after,
+        Look::WordStartHalfUnicode => !word_before,
+        Look::WordEndHalfUnicode => !word_after,
+    }
+}
+
+/// Returns which kinds of next character (including `END`) are compatible
+/// with `look`, given the kind of the previous character or 0 at the start
+/// of the text.
+fn look_next_kinds(look: Look, prev: u8) -> u8 {
+    let unicode_word = ASCII_WORD | UNICODE_WORD;
+    let (word_before, word) = match look {
+        Look::WordAscii | Look::WordAsciiNegate | Look::WordStartAscii |
+        Look::WordEndAscii | Look::WordStartHalfAscii |
+        Look::WordEndHalfAscii => (0 != prev & ASCII_WORD, ASCII_WORD),
+        _ => (0 != prev & unicode_word, unicode_word),
+    };
+    let not_word = ANY_NEXT & !word;
+    let if_start = |cond: bool| if cond { ANY_NEXT } else { 0 };
+
+    match look {
+        Look::Start => if_start(0 == prev),
+        Look::End => END,
+        Look::StartLF => if_start(0 == prev || NEWLINE == prev),
+        Look::EndLF => END | NEWLINE,
+        Look::StartCRLF => if CARRIAGE_RETURN == prev {
+            ANY_NEXT & !NEWLINE
+        } else {
+            if_start(0 == prev || NEWLINE == prev)
+        },
+        Look::EndCRLF => if CARRIAGE_RETURN == prev {
+            END | CARRIAGE_RETURN
+        } else {
+            END | CARRIAGE_RETURN | NEWLINE
+        },
+        Look::WordAscii | Look::WordUnicode =>
+            if word_before { not_word } else { word },
+        Look::WordAsciiNegate | Look::WordUnicodeNegate =>
+            if word_before { word } else { not_word },
+        Look::WordStartAscii | Look::WordStartUnicode =>
+            if word_before { 0 } else { word },
+        Look::WordEndAscii | Look::WordEndUnicode =>
+            if word_before { not_word } else { 0 },
+        Look::WordStartHalfAscii | Look::WordStartHalfUnicode =>
+            if_start(!word_before),
+        Look::WordEndHalfAscii | Look::WordEndHalfUnicode => not_word,
+    }
+}
+
+fn char_before(bytes: &[u8], at: usize) -> Option<char> {
+    (1..at.min(4) + 1).filter_map(
+        |len| str::from_utf8(&bytes[at - len..at]).ok())
+        .next().and_then(|s| s.chars().next())
+}
+
+fn char_after(bytes: &[u8], at: usize) -> Option<char> {
+    (1..(bytes.len() - at).min(4) + 1).filter_map(
+        |len| str::from_utf8(&bytes[at..at + len]).ok())
+        .next().and_then(|s| s.chars().next())
+}
+
+// To decide where assertions can hold, characters are classified into the
+// following kinds, each a bit so that sets of kinds can be represented as
+// `u8`s.
+const ASCII_WORD: u8 = 1;
+const UNICODE_WORD: u8 = 2;
+const OTHER: u8 = 4;
+const NEWLINE: u8 = 8;
+const CARRIAGE_RETURN: u8 = 16;
+/// Pseudo-kind for the end of the text.
+const END: u8 = 32;
+const ANY_NEXT: u8 = 63;
+
+fn char_kind(c: char) -> u8 {
+    if '\n' == c {
+        NEWLINE
+    } else if '\r' == c {
+        CARRIAGE_RETURN
+    } else if c.is_ascii() && rs::is_word_byte(c as u8) {
+        ASCII_WORD
+    } else if rs::is_word_character(c) {
+        UNICODE_WORD
+    } else {
+        OTHER
+    }
+}
+
+fn byte_kind(b: u8) -> u8 {
+    if b >= 0x80 {
+        // Could be part of a multi-byte character of either kind.
+        UNICODE_WORD | OTHER
+    } else {
+        char_kind(b as char)
+    }
+}
+
+/// Returns the kinds of the characters in `ranges`, given the ranges of
+/// characters matched by `\w`.
+fn range_kinds(ranges: &[(char, char)], word: &[(char, char)]) -> u8 {
+    let mut kinds = 0;
+    for &(start, end) in ranges {
+        let mut special_chars = 0;
+        for &(c, kind) in &[('\n', NEWLINE), ('\r', CARRIAGE_RETURN)] {
+            if start <= c && c <= end {
+                kinds |= kind;
+                special_chars += 1;
+            }
+        }
+
+        let mut word_chars = 0;
+        for &(word_start, word_end) in word {
+            let (lo, hi) = (word_start.max(start), word_end.min(end));
+            if lo <= hi {
+                word_chars += hi as u32 - lo as u32 + 1;
+                if lo.is_ascii() { kinds |= ASCII_WORD; }
+                if !hi.is_ascii() { kinds |= UNICODE_WORD; }
+            }
+        }
+
+        if end as u32 - start as u32 + 1 > word_chars + special_chars {
+            kinds |= OTHER;
+        }
+    }
+    kinds
+}
+
+fn byte_range_kinds(ranges: &[(u8, u8)]) -> u8 {
+    ranges.iter().fold(0, |kinds, &(start, end)| (start..=end).fold(
+        kinds, |kinds, b| kinds | byte_kind(b)))
+}
+
+/// Returns the kinds of the characters of a literal.
+fn literal_kinds(bytes: &[u8]) -> Vec<u8> {
+    match str::from_utf8(bytes) {
+        Ok(s) => s.chars().map(char_kind).collect(),
+        Err(_) => bytes.iter().map(|&b| byte_kind(b)).collect(),
+    }
+}
+
+/// The set of states the generator may be in between two characters.
+///
+/// Element `p` of the array is the set of constraints on the next character
+/// that may be in effect (each itself a set of kinds, used as a bit index)
+/// when the previous character has kind `1 << (p - 1)`, or when there is no
+/// previous character if `p` is 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct States([u64; 6]);
+
+impl States {
+    fn initial() -> Self {
+        States([1 << ANY_NEXT, 0, 0, 0, 0, 0])
+    }
+
+    fn empty() -> Self {
+        States([0; 6])
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|&set| 0 == set)
+    }
+
+    fn union(mut self, other: States) -> Self {
+        for (set, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *set |= *other;
+        }
+        self
+    }
+
+    fn constraints<'a>(&'a self) -> impl Iterator<Item = (u8, u8)> + 'a {
+        (0..6).flat_map(move |p| (0..64).filter(
+            move |&next| 0 != self.0[p] & (1 << next))
+            .map(move |next| (if 0 == p { 0 } else { 1 << (p - 1) },
+                              next as u8)))
+    }
+
+    fn can_end(&self) -> bool {
+        self.constraints().any(|(_, next)| 0 != next & END)
+    }
+
+    /// Returns the kinds of character which may come next.
+    fn next_kinds(&self) -> u8 {
+        self.constraints().fold(0, |kinds, (_, next)| kinds | next) & !END
+    }
+
+    /// Returns the states after consuming a character of one of the given
+    /// kinds.
+    fn consume(&self, kinds: u8) -> Self {
+        let mut states = States::empty();
+        let kinds = self.next_kinds() & kinds;
+        for p in 1..6 {
+            if 0 != kinds & (1 << (p - 1)) {
+                states.0[p] = 1 << ANY_NEXT;
+            }
+        }
+        states
+    }
+
+    fn assert(&self, look: Look) -> Self {
+        let mut states = States::empty();
+        for (prev, next) in self.constraints() {
+            let next = next & look_next_kinds(look, prev);
+            if 0 != next {
+                let p = if 0 == prev {
+                    0
+                } else {
+                    prev.trailing_zeros() as usize + 1
+                };
+                states.0[p] |= 1 << next;
+            }
+        }
+        states
+    }
+}
+
+/// Returns the states which may follow `expr` when it starts in one of
+/// `states`.
+fn reach(expr: &Hir, cx: &Context, states: States) -> States {
+    use self::rs::hir::HirKind::*;
+
+    if !cx.analyse {
+        return States::initial();
+    }
+
+    match *expr.kind() {
+        Empty => states,
+        Literal(rs::hir::Literal(ref bytes)) => literal_kinds(bytes).into_iter()
+            .fold(states, |states, kind| states.consume(kind)),
+        Class(rs::hir::Class::Unicode(ref class)) => states.consume(
+            range_kinds(&class_chars(class, cx.options).0, &cx.word)),
+        Class(rs::hir::Class::Bytes(ref class)) => states.consume(
+            byte_range_kinds(&class_bytes(class, cx.options))),
+        Look(look) => states.assert(look),
+        Capture(ref capture) => reach(&capture.sub, cx, states),
+        Repetition(ref repetition) =>
+            match repeat_range(repetition, cx.options) {
+                Ok(range) =>
+                    reach_repeat(&repetition.sub, cx, states, &range).1,
+                // Reported when compiling
+                Err(_) => states,
+            },
+        Concat(ref subs) => subs.iter().fold(
+            states, |states, sub| reach(sub, cx, states)),
+        Alternation(ref subs) => subs.iter().fold(
+            States::empty(), |accum, sub| accum.union(reach(sub, cx, states))),
+    }
+}
+
+/// Analyses repeating `e` a number of times within `range`, starting in one
+/// of `states`.
+///
+/// Returns the states in which an iteration may start and the states which
+/// may follow the whole repetition.
+fn reach_repeat(e: &Hir, cx: &Context, states: States,
+                range: &Range<usize>) -> (States, States) {
+    // Iterations are tracked exactly up to this count, and beyond it the
+    // analysis assumes the minimum number of repetitions is reached.
+    const MAX_TRACKED: usize = 256;
+
+    let mut starts = States::empty();
+    let mut after = States::empty();
+    // Iteration start states seen after the minimum count
+    let mut optional_starts = States::empty();
+    let mut current = states;
+    let mut count = 0;
+    while count < range.end {
+        if count >= range.start || count >= MAX_TRACKED {
+            after = after.union(current);
+            let new_optional_starts = optional_starts.union(current);
+            if new_optional_starts == optional_starts {
+                // Every successor of these states has already been
+                // accounted for.
+                break;
+            }
+            optional_starts = new_optional_starts;
+        }
+
+        if count + 1 >= range.end {
+            break;
+        }
+        starts = starts.union(current);
+        current = reach(e, cx, current);
+        count += 1;
+    }
+    (starts, after)
+}
+
+fn to_bytes(ch: char) -> Vec<u8> {
+    [ch].iter().map(|&c|c).collect::<String>().into_bytes()
 }
 
 #[cfg(test)]
@@ -262,10 +1214,17 @@ mod test {
 
     fn do_test(pattern: &str, min_distinct: usize, max_distinct: usize,
                iterations: usize) {
+        do_test_with(pattern, &RegexOptions::default(),
+                     min_distinct, max_distinct, iterations);
+    }
+
+    fn do_test_with(pattern: &str, options: &RegexOptions,
+                    min_distinct: usize, max_distinct: usize,
+                    iterations: usize) -> HashSet<String> {
         let rx = Regex::new(pattern).unwrap();
         let mut generated = HashSet::new();
 
-        let strategy = string_regex(pattern).unwrap();
+        let strategy = string_regex_with(pattern, options).unwrap();
         let mut runner = TestRunner::new(Config::default());
         for _ in 0..iterations {
             let mut value = strategy.new_value(&mut runner).unwrap();
@@ -294,6 +1253,7 @@ mod test {
         assert!(generated.len() <= max_distinct,
                 "Expected to generate at most {} strings, but \
                  generated {}", max_distinct, generated.len());
+        generated
     }
 
     #[test]
@@ -333,7 +1293,8 @@ mod test {
 
     #[test]
     fn test_n_to_range() {
-        do_test("a{4,}", 4, 4, 64);
+        // Up to `RegexOptions::max_repeat` more than the minimum
+        do_test("a{4,}", 33, 33, 256);
     }
 
     #[test]
@@ -360,4 +1321,280 @@ mod test {
     fn test_dot_s() {
         do_test("(?s).", 200, 65536, 256);
     }
+
+    #[test]
+    fn test_max_repeat() {
+        let options = RegexOptions {
+            max_repeat: 4, .. RegexOptions::default()
+        };
+        do_test_with("a*", &options, 5, 5, 64);
+        do_test_with("a+", &options, 4, 4, 64);
+        do_test_with("a{2,}", &options, 5, 5, 64);
+
+        let options = RegexOptions {
+            max_repeat: 1000, .. RegexOptions::default()
+        };
+        let mut runner = TestRunner::new(Config::default());
+        for &(pattern, max_len) in &[("a*", 1000), ("a{2,}", 1002)] {
+            let strategy = string_regex_with(pattern, &options).unwrap();
+            let lengths = (0..64).map(
+                |_| strategy.new_value(&mut runner).unwrap().current().len())
+                .collect::<Vec<_>>();
+            assert!(lengths.iter().all(|&len| len <= max_len));
+            assert!(lengths.iter().any(|&len| len > 500));
+        }
+    }
+
+    #[test]
+    fn test_casei_as_written() {
+        let options = RegexOptions {
+            case_insensitivity: CaseInsensitivity::AsWritten,
+            .. RegexOptions::default()
+        };
+        do_test_with("(?i:fOo)", &options, 1, 1, 16);
+    }
+
+    #[test]
+    fn test_dot_ascii() {
+        let options = RegexOptions {
+            dot: Dot::Ascii, .. RegexOptions::default()
+        };
+        let generated = do_test_with(".", &options, 64, 127, 256);
+        assert!(generated.iter().all(|s| s.chars().all(|c| c <= '\x7F')));
+
+        let options = RegexOptions {
+            dot: Dot::AsciiPrintable, .. RegexOptions::default()
+        };
+        let generated = do_test_with("(?s).", &options, 64, 95, 256);
+        assert!(generated.iter().all(
+            |s| s.chars().all(|c| (' '..='~').contains(&c))));
+    }
+
+    #[test]
+    fn test_dot_ranges() {
+        let options = RegexOptions {
+            dot: Dot::Ranges(vec![('\n', '\n'), ('a', 'c')]),
+            .. RegexOptions::default()
+        };
+        do_test_with(".", &options, 3, 3, 64);
+        do_test_with("(?s).", &options, 4, 4, 64);
+    }
+
+    #[test]
+    fn test_bytes_options() {
+        let options = RegexOptions {
+            max_repeat: 2,
+            dot: Dot::Ranges(vec![('0', '1'), ('\u{FF}', '\u{10FFFF}')]),
+            .. RegexOptions::default()
+        };
+        let strategy = bytes_regex_with("(?-u).*", &options).unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let bytes = strategy.new_value(&mut runner).unwrap().current();
+            assert!(bytes.len() <= 2);
+            assert!(bytes.iter().all(|&b| b'0' == b || b'1' == b || 0xFF == b),
+                    "Unexpected bytes {:?}", bytes);
+        }
+
+        let options = RegexOptions {
+            dot: Dot::Ranges(vec![('\u{100}', '\u{200}')]),
+            .. RegexOptions::default()
+        };
+        assert!(bytes_regex_with("(?-u).", &options).is_err());
+    }
+
+    #[test]
+    fn test_anchors() {
+        do_test("^foo$", 1, 1, 8);
+        do_test(r"\Afoo\z", 1, 1, 8);
+        do_test("^[0-9]{1,3}$", 100, 1000, 256);
+        do_test("(?m)^a$\n^b$", 1, 1, 8);
+        do_test("(^|x)y", 2, 2, 32);
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        do_test(r"\bfoo\b", 1, 1, 8);
+        do_test(r"[ab ]{0,2}\b[a ]", 5, 100, 256);
+        do_test(r"a\B[a ]", 1, 1, 16);
+        do_test(r".\b.", 100, 65536, 256);
+        do_test(r"(?-u:\b)[aé]{2}", 2, 4, 64);
+    }
+
+    #[test]
+    fn test_impossible_alternatives_are_skipped() {
+        do_test("(a^|b)c", 1, 1, 16);
+        do_test(r"x(\b|y)z", 1, 1, 16);
+    }
+
+    #[test]
+    fn test_unsatisfiable() {
+        for &pattern in &["a^b", r"foo$bar", r"\w\b\w", r"\W\b\W", r"a\Bb?$ ",
+                          r"(?m)a^", "(a^|b$)c", "(a^b){1,3}"] {
+            match string_regex(pattern) {
+                Err(Error::UnsatisfiableRegex(_)) => (),
+                Err(e) => panic!("Unexpected error for {:?}: {}", pattern, e),
+                Ok(_) => panic!("{:?} should be unsatisfiable", pattern),
+            }
+        }
+        assert!(string_regex("(a^b)*c").is_ok());
+    }
+
+    #[test]
+    fn test_unicode_categories() {
+        let generated = do_test_with(r"\p{Lu}", &RegexOptions::default(),
+                                     32, 4096, 256);
+        assert!(generated.iter().all(
+            |s| s.chars().all(|c| c.is_uppercase())));
+        do_test(r"\p{Script=Greek}\P{L}", 64, 65536, 256);
+    }
+
+    #[test]
+    fn test_class_set_operations() {
+        do_test("[a-z&&[^aeiou]]", 21, 21, 512);
+        do_test("[a-z--aeiou]", 21, 21, 512);
+        do_test("[0-9~~2-9]", 2, 2, 64);
+    }
+
+    #[test]
+    fn test_modern_syntax() {
+        do_test("(?<x>a)(?P<y>b)", 1, 1, 8);
+        do_test(r"\b{start}ab\b{end}", 1, 1, 8);
+        do_test(r"(?Rm)^a$\r\n^b$", 1, 1, 8);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        assert!(string_regex(r"(?-u:\xFF)").is_err());
+
+        let strategy = bytes_regex(r"(?-u:\xFF[\x80-\x81])").unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..16 {
+            let bytes = strategy.new_value(&mut runner).unwrap().current();
+            assert_eq!(0xFF, bytes[0]);
+            assert!(0x80 == bytes[1] || 0x81 == bytes[1]);
+        }
+    }
+
+    #[test]
+    fn test_not_matching() {
+        let rx = Regex::new("^(?:[0-9]{3}-[a-z]+)$").unwrap();
+        let strategy = not_matching("[0-9]{3}-[a-z]+").unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        let mut near_misses = 0;
+        for _ in 0..256 {
+            let mut value = strategy.new_value(&mut runner).unwrap();
+            let s = value.current();
+            if s.chars().filter(|&c| '-' == c).count() == 1 {
+                near_misses += 1;
+            }
+
+            loop {
+                let s = value.current();
+                assert!(!rx.is_match(&s), "{:?} matches", s);
+                if !value.simplify() { break; }
+            }
+        }
+        // Most edits leave the dash alone.
+        assert!(near_misses > 128, "Only {} near misses", near_misses);
+    }
+
+    #[test]
+    fn test_not_matching_with_trailing_comment() {
+        let strategy = not_matching("(?x) abc # trailing comment").unwrap();
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let s = strategy.new_value(&mut runner).unwrap().current();
+            assert_ne!("abc", s);
+        }
+    }
+
+    #[test]
+    fn test_not_matching_everything() {
+        let strategy = not_matching("(?s).*").unwrap();
+        let mut runner = TestRunner::new(Config {
+            max_local_rejects: 256, .. Config::default()
+        });
+        assert!(strategy.new_value(&mut runner).is_err());
+    }
+
+    #[test]
+    fn test_string_of() {
+        let strategy = string_of(char::range('a', 'z'), 5..20);
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let mut value = strategy.new_value(&mut runner).unwrap();
+            loop {
+                let s = value.current();
+                let len = s.chars().count();
+                assert!((5..20).contains(&len), "Bad length: {:?}", s);
+                if !value.simplify() { break; }
+            }
+            assert_eq!("aaaaa", value.current());
+        }
+    }
+
+    #[test]
+    fn test_string_of_utf8_len() {
+        let strategy = string_of_utf8_len(char::ANY, 5..12);
+        let mut runner = TestRunner::new(Config::default());
+        let mut multibyte = 0;
+        for _ in 0..256 {
+            let mut value = strategy.new_value(&mut runner).unwrap();
+            if value.current().len() > value.current().chars().count() {
+                multibyte += 1;
+            }
+
+            loop {
+                let s = value.current();
+                assert!((5..12).contains(&s.len()), "Bad length: {:?}", s);
+                if !value.simplify() { break; }
+            }
+        }
+        assert!(multibyte > 0);
+    }
+
+    #[test]
+    fn test_string_of_shrinks_to_failure() {
+        let strategy = string_of_utf8_len(char::ANY, 0..64);
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let case = strategy.new_value(&mut runner).unwrap();
+            let result = runner.run_one(case, |s| {
+                if s.is_ascii() {
+                    Ok(())
+                } else {
+                    Err(TestCaseError::Fail("non-ASCII".to_owned()))
+                }
+            });
+
+            match result {
+                Ok(_) => (),
+                Err(TestError::Fail(_, s)) => {
+                    assert_eq!(1, s.chars().count(), "Not minimal: {:?}", s);
+                    assert!(!s.is_ascii(), "Shrunk to passing {:?}", s);
+                },
+                e => panic!("Unexpected result: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_string_of_utf8_len_impossible() {
+        let strategy = string_of_utf8_len(char::range('\u{800}', '\u{FFFF}'),
+                                          4..6);
+        let mut runner = TestRunner::new(Config {
+            max_local_rejects: 256, .. Config::default()
+        });
+        assert!(strategy.new_value(&mut runner).is_err());
+    }
+
+    #[test]
+    fn test_string_of_empty_size() {
+        let mut runner = TestRunner::new(Config::default());
+        assert!(string_of(char::ANY, 3..3).new_value(&mut runner).is_err());
+        let reversed = Range { start: 5, end: 2 };
+        assert!(string_of_utf8_len(char::ANY, reversed).new_value(&mut runner)
+                .is_err());
+    }
 }
@@ -145,11 +145,16 @@ macro_rules! prop_assume {
 /// assumption that earlier ones are "simpler", so they should be listed in
 /// order of ascending complexity when possible.
 ///
+/// Unlike `Union`, the strategies do not need to be of the same type, only to
+/// produce the same type of value, and there is no limit on how many may be
+/// given. No boxing takes place; inputs beyond the tenth are handled by
+/// nesting `TupleUnion`s.
+///
 /// The macro invocation has two forms. The first is to simply list the
 /// strategies separated by commas; this will cause value generation to pick
-/// from the strategies uniformly. The other form is to provide a weight in the
-/// form of a `u32` before each strategy, separated from the strategy with
-/// `=>`.
+/// from the strategies uniformly. The other form is to provide a weight before
+/// each strategy, separated from the strategy with `=>`. Weights may be any
+/// expression evaluating to a `u32`, and each is evaluated exactly once.
 ///
 /// Note that the exact type returned by the macro varies depending on how many
 /// inputs there are. In particular, if given exactly one option, it will
@@ -199,25 +204,29 @@ macro_rules! prop_oneof {
 
     ($weight0:expr => $item0:expr,
      $weight1:expr => $item1:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1))))
     };
 
     ($weight0:expr => $item0:expr,
      $weight1:expr => $item1:expr,
      $weight2:expr => $item2:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1),
-             ($weight2, $item2)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2))))
     };
 
     ($weight0:expr => $item0:expr,
      $weight1:expr => $item1:expr,
      $weight2:expr => $item2:expr,
      $weight3:expr => $item3:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1),
-             ($weight2, $item2), ($weight3, $item3)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2)),
+            ($weight3, ::std::sync::Arc::new($item3))))
     };
 
     ($weight0:expr => $item0:expr,
@@ -225,10 +234,12 @@ macro_rules! prop_oneof {
      $weight2:expr => $item2:expr,
      $weight3:expr => $item3:expr,
      $weight4:expr => $item4:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1),
-             ($weight2, $item2), ($weight3, $item3),
-             ($weight4, $item4)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2)),
+            ($weight3, ::std::sync::Arc::new($item3)),
+            ($weight4, ::std::sync::Arc::new($item4))))
     };
 
     ($weight0:expr => $item0:expr,
@@ -237,10 +248,13 @@ macro_rules! prop_oneof {
      $weight3:expr => $item3:expr,
      $weight4:expr => $item4:expr,
      $weight5:expr => $item5:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1),
-             ($weight2, $item2), ($weight3, $item3),
-             ($weight4, $item4), ($weight5, $item5)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2)),
+            ($weight3, ::std::sync::Arc::new($item3)),
+            ($weight4, ::std::sync::Arc::new($item4)),
+            ($weight5, ::std::sync::Arc::new($item5))))
     };
 
     ($weight0:expr => $item0:expr,
@@ -250,11 +264,14 @@ macro_rules! prop_oneof {
      $weight4:expr => $item4:expr,
      $weight5:expr => $item5:expr,
      $weight6:expr => $item6:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1),
-             ($weight2, $item2), ($weight3, $item3),
-             ($weight4, $item4), ($weight5, $item5),
-             ($weight6, $item6)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2)),
+            ($weight3, ::std::sync::Arc::new($item3)),
+            ($weight4, ::std::sync::Arc::new($item4)),
+            ($weight5, ::std::sync::Arc::new($item5)),
+            ($weight6, ::std::sync::Arc::new($item6))))
     };
 
     ($weight0:expr => $item0:expr,
@@ -265,11 +282,15 @@ macro_rules! prop_oneof {
      $weight5:expr => $item5:expr,
      $weight6:expr => $item6:expr,
      $weight7:expr => $item7:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1),
-             ($weight2, $item2), ($weight3, $item3),
-             ($weight4, $item4), ($weight5, $item5),
-             ($weight6, $item6), ($weight7, $item7)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2)),
+            ($weight3, ::std::sync::Arc::new($item3)),
+            ($weight4, ::std::sync::Arc::new($item4)),
+            ($weight5, ::std::sync::Arc::new($item5)),
+            ($weight6, ::std::sync::Arc::new($item6)),
+            ($weight7, ::std::sync::Arc::new($item7))))
     };
 
     ($weight0:expr => $item0:expr,
@@ -281,12 +302,16 @@ macro_rules! prop_oneof {
      $weight6:expr => $item6:expr,
      $weight7:expr => $item7:expr,
      $weight8:expr => $item8:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1),
-             ($weight2, $item2), ($weight3, $item3),
-             ($weight4, $item4), ($weight5, $item5),
-             ($weight6, $item6), ($weight7, $item7),
-             ($weight8, $item8)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2)),
+            ($weight3, ::std::sync::Arc::new($item3)),
+            ($weight4, ::std::sync::Arc::new($item4)),
+            ($weight5, ::std::sync::Arc::new($item5)),
+            ($weight6, ::std::sync::Arc::new($item6)),
+            ($weight7, ::std::sync::Arc::new($item7)),
+            ($weight8, ::std::sync::Arc::new($item8))))
     };
 
     ($weight0:expr => $item0:expr,
@@ -299,19 +324,44 @@ macro_rules! prop_oneof {
      $weight7:expr => $item7:expr,
      $weight8:expr => $item8:expr,
      $weight9:expr => $item9:expr $(,)*) => {
-        $crate::strategy::TupleUnion::new(
-            (($weight0, $item0), ($weight1, $item1),
-             ($weight2, $item2), ($weight3, $item3),
-             ($weight4, $item4), ($weight5, $item5),
-             ($weight6, $item6), ($weight7, $item7),
-             ($weight8, $item8), ($weight9, $item9)))
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2)),
+            ($weight3, ::std::sync::Arc::new($item3)),
+            ($weight4, ::std::sync::Arc::new($item4)),
+            ($weight5, ::std::sync::Arc::new($item5)),
+            ($weight6, ::std::sync::Arc::new($item6)),
+            ($weight7, ::std::sync::Arc::new($item7)),
+            ($weight8, ::std::sync::Arc::new($item8)),
+            ($weight9, ::std::sync::Arc::new($item9))))
     };
 
-    ($($weight:expr => $item:expr),+ $(,)*) => {
-        $crate::strategy::Union::new_weighted(vec![
-            $(($weight, $crate::strategy::Strategy::boxed($item))),*
-        ])
-    };
+    ($weight0:expr => $item0:expr,
+     $weight1:expr => $item1:expr,
+     $weight2:expr => $item2:expr,
+     $weight3:expr => $item3:expr,
+     $weight4:expr => $item4:expr,
+     $weight5:expr => $item5:expr,
+     $weight6:expr => $item6:expr,
+     $weight7:expr => $item7:expr,
+     $weight8:expr => $item8:expr,
+     $($weight_rest:expr => $item_rest:expr),+ $(,)*) => { {
+        // Any options beyond the ninth are nested into a union of their own,
+        // which is placed last so that shrinking order is unchanged.
+        let rest = prop_oneof![$($weight_rest => $item_rest),*];
+        $crate::strategy::TupleUnion::new((
+            ($weight0, ::std::sync::Arc::new($item0)),
+            ($weight1, ::std::sync::Arc::new($item1)),
+            ($weight2, ::std::sync::Arc::new($item2)),
+            ($weight3, ::std::sync::Arc::new($item3)),
+            ($weight4, ::std::sync::Arc::new($item4)),
+            ($weight5, ::std::sync::Arc::new($item5)),
+            ($weight6, ::std::sync::Arc::new($item6)),
+            ($weight7, ::std::sync::Arc::new($item7)),
+            ($weight8, ::std::sync::Arc::new($item8)),
+            (rest.total_weight(), ::std::sync::Arc::new(rest))))
+    } };
 }
 
 /// Convenience to define functions which produce new strategies.
@@ -922,10 +972,6 @@ mod test {
                             -> ::strategy::TupleUnion<T>
         { v }
 
-        fn assert_dynamic<T : ::strategy::Strategy>
-            (v: ::strategy::Union<T>) -> ::strategy::Union<T>
-        { v }
-
         use strategy::Just as J;
         expect_count(1, prop_oneof![J(0i32)]);
         expect_count(2, assert_static(prop_oneof![
@@ -1000,7 +1046,7 @@ mod test {
             J(8i32),
             J(9i32),
         ]));
-        expect_count(11, assert_dynamic(prop_oneof![
+        expect_count(11, assert_static(prop_oneof![
             J(0i32),
             J(1i32),
             J(2i32),
@@ -1014,4 +1060,58 @@ mod test {
             J(10i32),
         ]));
     }
+
+    #[test]
+    fn oneof_many_mixed_options() {
+        use std::collections::HashSet;
+        use strategy::*;
+        use test_runner::*;
+
+        let base = 3u32;
+        let input = prop_oneof![
+            base => Just(0i32),
+            1 => Just(1i32),
+            1 => 2i32..3,
+            1 => Just(3i32),
+            1 => Just(2i32).prop_map(|v| v * 2),
+            1 => Just(5i32),
+            1 => 6i32..7,
+            1 => Just(7i32),
+            1 => Just(8i32),
+            1 => Just(9i32),
+            1 => Just(10i32),
+            1 => 11i32..12,
+            1 => Just(12i32),
+            1 => Just(13i32).prop_map(|v| v),
+            base - 2 => Just(14i32),
+            1 => Just(15i32),
+            1 => Just(16i32),
+            1 => Just(17i32),
+            1 => Just(18i32),
+            1 => Just(19i32),
+            1 => Just(20i32),
+            1 => Just(21i32),
+            1 => Just(22i32),
+            1 => Just(23i32),
+            1 => Just(24i32),
+        ];
+
+        let mut runner = TestRunner::new(Config::default());
+        let mut seen = HashSet::new();
+        for _ in 0..4096 {
+            let mut case = input.new_value(&mut runner).unwrap();
+            seen.insert(case.current());
+
+            // Shrinking should walk back through every earlier option in
+            // turn, even across the nested unions.
+            let mut prev = case.current();
+            while case.simplify() {
+                assert_eq!(prev - 1, case.current());
+                prev = case.current();
+            }
+            assert_eq!(0, case.current());
+        }
+
+        assert_eq!(25, seen.len());
+    }
 }
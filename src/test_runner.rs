@@ -19,7 +19,7 @@ use std::sync::Arc;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::SeqCst;
 
-use rand::{self, XorShiftRng};
+use rand::{self, Rng, XorShiftRng};
 
 use strategy::*;
 
@@ -47,6 +47,13 @@ pub struct Config {
     ///
     /// The default is 1000000.
     pub max_flat_map_regens: u32,
+    /// The probability that the integer strategies in `num` produce one of a
+    /// handful of special values, such as the bounds of the range, zero, one
+    /// or a power of two, instead of a uniformly chosen one. Setting this to
+    /// 0 makes them sample uniformly.
+    ///
+    /// The default is 0.1.
+    pub edge_bias: f64,
     // Needs to be public so FRU syntax can be used.
     #[doc(hidden)]
     pub _non_exhaustive: (),
@@ -59,6 +66,7 @@ impl Default for Config {
             max_local_rejects: 65536,
             max_global_rejects: 1024,
             max_flat_map_regens: 1000000,
+            edge_bias: 0.1,
             _non_exhaustive: (),
         }
     }
@@ -223,6 +231,12 @@ impl TestRunner {
         &mut self.rng
     }
 
+    /// Create a new, independent but deterministic RNG from the RNG in this
+    /// runner.
+    pub fn new_rng(&mut self) -> XorShiftRng {
+        self.rng.gen()
+    }
+
     /// Returns the configuration of this runner.
     pub fn config(&self) -> &Config {
         &self.config
@@ -0,0 +1,372 @@
+//-
+// Copyright 2017 Jason Lingle
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Strategies for generating text with complex Unicode structure.
+//!
+//! `char::ANY` picks single code points, so strings built from it rarely
+//! contain combining sequences, emoji sequences or text which changes under
+//! normalization. The strategies here generate such text directly, one
+//! grapheme cluster (i.e., user-perceived character) at a time, so that
+//! values shrink by removing and simplifying whole clusters.
+
+use std::borrow::Cow;
+use std::char as stdchar;
+use std::iter;
+use std::ops::Range;
+
+use char::{self, CharStrategy};
+use collection;
+use strategy::*;
+use test_runner::*;
+
+opaque_strategy_wrapper! {
+    /// Strategy which generates text with complex Unicode structure.
+    ///
+    /// Created by various functions in this module.
+    #[derive(Debug)]
+    pub struct TextStrategy[][](BoxedStrategy<String>) -> TextValueTree;
+    /// `ValueTree` corresponding to `TextStrategy`.
+    pub struct TextValueTree[][](Box<ValueTree<Value = String>>) -> String;
+}
+
+type CharRange = (char, char);
+
+/// Letters which combining marks are placed on.
+const BASE_RANGES: &[CharRange] = &[
+    ('A', 'Z'), ('a', 'z'), ('\u{C0}', '\u{24F}'),
+    ('\u{391}', '\u{3C9}'), ('\u{410}', '\u{44F}'),
+];
+
+/// Marks with particularly common or interesting uses: acute, diaeresis,
+/// dot below, cedilla, enclosing circle and the grapheme joiner.
+const SPECIAL_COMBINING_MARKS: &[char] = &[
+    '\u{301}', '\u{308}', '\u{323}', '\u{327}', '\u{20DD}', '\u{34F}',
+];
+
+const COMBINING_MARK_RANGES: &[CharRange] = &[
+    ('\u{300}', '\u{36F}'), ('\u{1DC0}', '\u{1DF9}'),
+    ('\u{20D0}', '\u{20F0}'), ('\u{FE20}', '\u{FE2F}'),
+];
+
+const BIDI_CONTROL_RANGES: &[CharRange] = &[
+    // ALM, LRM and RLM
+    ('\u{61C}', '\u{61C}'), ('\u{200E}', '\u{200F}'),
+    // Embeddings, overrides and isolates, with their terminators
+    ('\u{202A}', '\u{202E}'), ('\u{2066}', '\u{2069}'),
+];
+
+/// Emoji which accept skin tone modifiers and take part in ZWJ sequences:
+/// boy, girl, man, woman, and hand gestures.
+const PEOPLE_EMOJI: &[CharRange] = &[
+    ('\u{1F466}', '\u{1F469}'), ('\u{1F44B}', '\u{1F44F}'),
+];
+
+/// Characters whose composed and decomposed forms differ, in the form
+/// `(composed, decomposed)`, with the marks of the decomposed form in
+/// canonical order.
+const COMPOSITIONS: &[(&str, &str)] = &[
+    ("\u{E9}", "e\u{301}"),
+    ("\u{F1}", "n\u{303}"),
+    ("\u{C5}", "A\u{30A}"),
+    ("\u{F6}", "o\u{308}"),
+    ("\u{E7}", "c\u{327}"),
+    ("\u{1E0D}", "d\u{323}"),
+    ("\u{1EC7}", "e\u{323}\u{302}"),
+    ("\u{1D6}", "u\u{308}\u{304}"),
+    ("\u{3CE}", "\u{3C9}\u{301}"),
+    ("\u{439}", "\u{438}\u{306}"),
+];
+
+/// Base characters followed by two combining marks of different canonical
+/// combining classes, in canonical order. Unlike marks of the same class,
+/// which change the meaning when swapped, these are put back in this order
+/// by normalization.
+const REORDERABLE: &[&str] = &[
+    "e\u{323}\u{302}",
+    "a\u{323}\u{306}",
+    "o\u{31B}\u{301}",
+    "u\u{31B}\u{303}",
+    "a\u{328}\u{301}",
+];
+
+/// Characters which normalization always replaces: angstrom, ohm and kelvin
+/// signs, and the Greek question mark.
+const SINGLETONS: &[char] = &['\u{212B}', '\u{2126}', '\u{212A}', '\u{37E}'];
+
+/// Creates a `CharStrategy` which generates combining marks, such as
+/// U+0301 COMBINING ACUTE ACCENT.
+///
+/// On its own, a combining mark forms an unusual grapheme cluster; place it
+/// after a base character to modify that instead.
+pub fn combining_mark() -> CharStrategy<'static> {
+    CharStrategy::new_borrowed(SPECIAL_COMBINING_MARKS, &[],
+                               COMBINING_MARK_RANGES)
+}
+
+/// Creates a `CharStrategy` which generates the explicit bidirectional
+/// formatting characters, such as U+202E RIGHT-TO-LEFT OVERRIDE.
+pub fn bidi_control() -> CharStrategy<'static> {
+    CharStrategy::new_borrowed(&[], &[], BIDI_CONTROL_RANGES)
+}
+
+/// Creates a strategy which generates a single grapheme cluster.
+///
+/// Values may be a single character from `char::ANY`, a letter with combining
+/// marks, a character in composed or decomposed form, Hebrew or Arabic
+/// letters with vowel marks, an emoji with a skin tone modifier, a flag, an
+/// emoji ZWJ sequence (such as a family), or a bidirectional formatting
+/// character. Values shrink toward the kinds earlier in this list.
+pub fn grapheme_cluster() -> TextStrategy {
+    TextStrategy(Union::new_weighted(vec![
+        (4, char::ANY.prop_map(|c| c.to_string()).boxed()),
+        (2, combining_sequence()),
+        (2, normalization_cluster()),
+        (1, rtl_cluster()),
+        (1, (char::ranges(Cow::Borrowed(PEOPLE_EMOJI)),
+             char::range('\u{1F3FB}', '\u{1F3FF}'))
+         .prop_map(|(emoji, tone)| [emoji, tone].iter().collect())
+         .boxed()),
+        (1, (char::range('\u{1F1E6}', '\u{1F1FF}'),
+             char::range('\u{1F1E6}', '\u{1F1FF}'))
+         .prop_map(|(a, b)| [a, b].iter().collect())
+         .boxed()),
+        (1, collection::vec(char::ranges(Cow::Borrowed(PEOPLE_EMOJI)), 2..5)
+         .prop_map(|emoji| emoji.iter().map(|e| e.to_string())
+                   .collect::<Vec<_>>().join("\u{200D}"))
+         .boxed()),
+        (1, bidi_control().prop_map(|c| c.to_string()).boxed()),
+    ]).boxed())
+}
+
+/// Creates a strategy which generates strings of grapheme clusters from
+/// `grapheme_cluster()`, with the number of clusters within `size`.
+///
+/// Adjacent clusters occasionally merge into one, for example when `\r` is
+/// followed by `\n` or a lone combining mark follows another cluster, which
+/// text handling must also cope with. Values shrink by removing whole
+/// clusters and then simplifying those that remain.
+pub fn graphemes(size: Range<usize>) -> TextStrategy {
+    TextStrategy(collection::vec(grapheme_cluster(), size)
+                 .prop_map(|clusters| clusters.concat())
+                 .boxed())
+}
+
+/// Creates a strategy which generates strings of `size` grapheme clusters
+/// which are each changed by NFC or NFD normalization, interspersed with
+/// ASCII text.
+///
+/// Clusters are characters in composed and decomposed form, combining marks
+/// out of canonical order, Hangul syllables as single characters or as
+/// conjoining jamo, and characters such as U+212B ANGSTROM SIGN which
+/// normalization always replaces.
+pub fn normalization_sensitive(size: Range<usize>) -> TextStrategy {
+    let ascii = collection::vec(char::range(' ', '~'), 0..4);
+    TextStrategy(collection::vec((ascii, normalization_cluster()), size)
+                 .prop_map(|parts| parts.into_iter()
+                           .map(|(ascii, cluster)| ascii.into_iter()
+                                .collect::<String>() + &cluster)
+                           .collect())
+                 .boxed())
+}
+
+/// Creates a strategy which generates strings mixing left-to-right and
+/// right-to-left words, digits, spaces and bidirectional formatting
+/// characters, with the number of such parts within `size`.
+///
+/// Formatting characters are not necessarily balanced, so an embedding may
+/// be left open, or a terminator may occur without one.
+pub fn bidi_text(size: Range<usize>) -> TextStrategy {
+    let part = Union::new(vec![
+        collection::vec(char::range('a', 'z'), 1..6)
+            .prop_map(|word| word.into_iter().collect()).boxed(),
+        collection::vec(rtl_cluster(), 1..6)
+            .prop_map(|word| word.concat()).boxed(),
+        collection::vec(char::range('0', '9'), 1..4)
+            .prop_map(|digits| digits.into_iter().collect()).boxed(),
+        Just(" ".to_owned()).boxed(),
+        bidi_control().prop_map(|c| c.to_string()).boxed(),
+    ]);
+    TextStrategy(collection::vec(part, size)
+                 .prop_map(|parts| parts.concat())
+                 .boxed())
+}
+
+fn combining_sequence() -> BoxedStrategy<String> {
+    (CharStrategy::new_borrowed(&[], char::DEFAULT_PREFERRED_RANGES,
+                                BASE_RANGES),
+     collection::vec(combining_mark(), 1..4))
+        .prop_map(|(base, marks)| iter::once(base).chain(marks).collect())
+        .boxed()
+}
+
+fn normalization_cluster() -> BoxedStrategy<String> {
+    Union::new(vec![
+        (0..COMPOSITIONS.len(), 0..2).prop_map(|(ix, form)| {
+            let (composed, decomposed) = COMPOSITIONS[ix];
+            match form {
+                0 => composed.to_owned(),
+                _ => decomposed.to_owned(),
+            }
+        }).boxed(),
+        (0..REORDERABLE.len()).prop_map(|ix| reverse_marks(REORDERABLE[ix]))
+            .boxed(),
+        (0xAC00..0xD7A4u32, 0..2).prop_map(|(syllable, form)| match form {
+            0 => stdchar::from_u32(syllable).expect("bad syllable")
+                .to_string(),
+            _ => decompose_hangul(syllable),
+        }).boxed(),
+        (0..SINGLETONS.len()).prop_map(|ix| SINGLETONS[ix].to_string())
+            .boxed(),
+    ]).boxed()
+}
+
+/// Reverses the marks following the base character of `cluster`.
+fn reverse_marks(cluster: &str) -> String {
+    let mut chars = cluster.chars();
+    let base = chars.next().expect("empty cluster");
+    iter::once(base).chain(chars.rev()).collect()
+}
+
+fn rtl_cluster() -> BoxedStrategy<String> {
+    Union::new(vec![
+        // Hebrew letters and points
+        (char::range('\u{5D0}', '\u{5EA}'),
+         collection::vec(char::range('\u{5B0}', '\u{5BC}'), 0..2))
+            .prop_map(|(letter, points)|
+                      iter::once(letter).chain(points).collect())
+            .boxed(),
+        // Arabic letters and harakat
+        (char::range('\u{627}', '\u{64A}'),
+         collection::vec(char::range('\u{64B}', '\u{652}'), 0..2))
+            .prop_map(|(letter, marks)|
+                      iter::once(letter).chain(marks).collect())
+            .boxed(),
+    ]).boxed()
+}
+
+/// Returns the conjoining jamo for the given Hangul syllable, as described
+/// in section 3.12 of the Unicode standard.
+fn decompose_hangul(syllable: u32) -> String {
+    let index = syllable - 0xAC00;
+    let lead = 0x1100 + index / (21 * 28);
+    let vowel = 0x1161 + index % (21 * 28) / 28;
+    let trail = 0x11A7 + index % 28;
+
+    let mut jamo = vec![lead, vowel];
+    if trail > 0x11A7 {
+        jamo.push(trail);
+    }
+    jamo.into_iter()
+        .map(|j| stdchar::from_u32(j).expect("bad jamo"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use unicode_normalization::UnicodeNormalization;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use super::*;
+
+    fn check_all<F : Fn (&str)>(strategy: TextStrategy, check: F) {
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..256 {
+            let mut value = strategy.new_value(&mut runner).unwrap();
+            loop {
+                check(&value.current());
+                if !value.simplify() { break; }
+            }
+        }
+    }
+
+    #[test]
+    fn clusters_are_single_graphemes() {
+        check_all(grapheme_cluster(), |s| {
+            assert_eq!(1, s.graphemes(true).count(),
+                       "Not a single cluster: {:?}", s);
+        });
+    }
+
+    #[test]
+    fn graphemes_has_about_size_clusters() {
+        check_all(graphemes(4..8), |s| {
+            assert!(s.graphemes(true).count() < 8, "Too long: {:?}", s);
+        });
+    }
+
+    #[test]
+    fn normalization_changes_text() {
+        check_all(normalization_sensitive(1..4), |s| {
+            assert!(s.nfc().collect::<String>() != s ||
+                    s.nfd().collect::<String>() != s,
+                    "Normalization has no effect on {:?}", s);
+        });
+    }
+
+    #[test]
+    fn hangul_decomposes_like_nfd() {
+        for &syllable in &[0xAC00, 0xAC01, 0xD7A3, 0xB098] {
+            let c = stdchar::from_u32(syllable).unwrap();
+            assert_eq!(c.to_string().nfd().collect::<String>(),
+                       decompose_hangul(syllable));
+        }
+        for &(composed, decomposed) in COMPOSITIONS {
+            assert_eq!(decomposed, composed.nfd().collect::<String>());
+        }
+    }
+
+    #[test]
+    fn reversed_marks_are_only_reordered() {
+        for &cluster in REORDERABLE {
+            let reversed = reverse_marks(cluster);
+            assert_ne!(cluster, reversed);
+            assert_eq!(cluster, reversed.nfd().collect::<String>());
+        }
+    }
+
+    #[test]
+    fn bidi_text_has_both_directions() {
+        let strategy = bidi_text(4..8);
+        let mut runner = TestRunner::new(Config::default());
+        let mut mixed = 0;
+        for _ in 0..256 {
+            let s = strategy.new_value(&mut runner).unwrap().current();
+            if s.chars().any(|c| c.is_ascii_alphabetic()) &&
+                s.chars().any(|c| ('\u{5D0}'..='\u{64A}').contains(&c))
+            {
+                mixed += 1;
+            }
+        }
+        assert!(mixed > 64, "Only {} mixed", mixed);
+    }
+
+    #[test]
+    fn shrinks_whole_clusters() {
+        let strategy = graphemes(0..16);
+        let mut runner = TestRunner::new(Config::default());
+        for _ in 0..64 {
+            let case = strategy.new_value(&mut runner).unwrap();
+            // Pretend that any string containing a combining sequence fails.
+            let result = runner.run_one(case, |s| {
+                if s.graphemes(true).any(|g| g.chars().count() > 1) {
+                    Err(TestCaseError::Fail("multi-char cluster".to_owned()))
+                } else {
+                    Ok(())
+                }
+            });
+
+            match result {
+                Ok(_) => (),
+                Err(TestError::Fail(_, s)) => assert_eq!(
+                    1, s.graphemes(true).count(), "Not minimal: {:?}", s),
+                e => panic!("Unexpected result: {:?}", e),
+            }
+        }
+    }
+}